@@ -0,0 +1,58 @@
+// Compares the ways to transpose a `Matrix`, from matrices that fit in L1
+// to ones far larger than any cache: `cargo bench --bench transpose`.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use rust_wasm::matrix::Matrix;
+
+const SIZES: [usize; 5] = [16, 64, 256, 1024, 4096];
+
+fn matrix(rows: usize, cols: usize) -> Matrix<f64> {
+  Matrix::from_fn(rows, cols, |row, col| (row * cols + col) as f64)
+}
+
+fn square(c: &mut Criterion) {
+  let mut group = c.benchmark_group("transpose square");
+  for size in SIZES {
+    let source = matrix(size, size);
+    group.throughput(Throughput::Elements((size * size) as u64));
+    if size >= 1024 {
+      group.sample_size(10);
+    }
+    group.bench_with_input(BenchmarkId::new("naive", size), &source, |b, m| {
+      b.iter(|| m.transpose())
+    });
+    group.bench_with_input(BenchmarkId::new("blocked", size), &source, |b, m| {
+      b.iter(|| m.transpose_blocked())
+    });
+    // Transposing twice restores the input, so one matrix serves every
+    // iteration.
+    let mut in_place = source.clone();
+    group.bench_function(BenchmarkId::new("in place", size), |b| {
+      b.iter(|| in_place.transpose_in_place())
+    });
+  }
+  group.finish();
+}
+
+fn rectangular(c: &mut Criterion) {
+  let mut group = c.benchmark_group("transpose rectangular");
+  for size in SIZES {
+    let (rows, cols) = (size, size / 2);
+    let source = matrix(rows, cols);
+    group.throughput(Throughput::Elements((rows * cols) as u64));
+    if size >= 1024 {
+      group.sample_size(10);
+    }
+    let id = |name| BenchmarkId::new(name, format!("{rows}x{cols}"));
+    group.bench_with_input(id("naive"), &source, |b, m| b.iter(|| m.transpose()));
+    group.bench_with_input(id("blocked"), &source, |b, m| {
+      b.iter(|| m.transpose_blocked())
+    });
+    let mut in_place = source.clone();
+    group.bench_function(id("in place"), |b| b.iter(|| in_place.transpose_in_place()));
+  }
+  group.finish();
+}
+
+criterion_group!(benches, square, rectangular);
+criterion_main!(benches);
@@ -0,0 +1,9 @@
+//! `link-checker URL...`: crawl from the given URLs and exit non-zero if any
+//! link is broken. See `--help` for the options.
+use std::process::ExitCode;
+
+use rust_wasm::link_checker::cli;
+
+fn main() -> ExitCode {
+  cli::main()
+}
@@ -9,3 +9,19 @@ extern "C" {
 pub fn greet(name: &str) {
   alert(&format!("Hello, {}!", name));
 }
+
+// The link checker and the matrix type are also used natively: by the
+// `link-checker` binary, the `rust-wasm` exercises, the integration tests and
+// the benchmarks. They need threads and sockets, so wasm builds leave them out.
+#[cfg(not(target_arch = "wasm32"))]
+mod utils {
+  pub mod exercises {
+    pub mod multi_threaded_link_checker;
+  }
+  pub mod matrix;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use utils::exercises::multi_threaded_link_checker as link_checker;
+#[cfg(not(target_arch = "wasm32"))]
+pub use utils::matrix;
@@ -6,6 +6,7 @@ mod race;
 mod utils;
 
 use utils::exercises::storing_book::library;
+use utils::matrix::Matrix;
 
 fn main() {
   // dereference
@@ -118,7 +119,10 @@ fn main() {
   // utils::exercises::simple_gui_library::simple_gui_library();
   // utils::exercises::polygon_struct::polygon_struct();
   // utils::exercises::dining_philosophers::dining_philosophers();
-  utils::exercises::multi_threaded_link_checker::link_checker();
+  if let Err(error) = utils::exercises::multi_threaded_link_checker::link_checker() {
+    eprintln!("{error}");
+  }
+  // utils::exercises::multi_threaded_link_checker::async_crawler::async_link_checker();
 }
 
 // Array, Vec, Slice
@@ -130,59 +134,51 @@ fn sample() {
     [301, 302, 303],
   ];
 
+  let matrix = Matrix::from(matrix);
   println!("matrix:");
-  pretty_print(&matrix);
+  print!("{matrix}");
 
-  let transposed = transpose(&matrix);
+  let transposed = matrix.transpose();
   println!("transposed:");
-  pretty_print(&transposed);
+  print!("{transposed}");
 
   // advanced matrix practice
   pretty_print(&[&[1, 2, 3], &[4, 5, 6], &[7, 8, 9]]);
   pretty_print([["a", "b"], ["c", "d"]]);
-  pretty_print(vec![vec![1, 2], vec![3, 4]])
+  pretty_print(vec![vec![1, 2], vec![3, 4]]);
+  pretty_print(Matrix::from([[1, 2, 3], [4, 5, 6]]).transpose().row_iter())
 }
 
-fn transpose(matrix: &[[i32; 3]; 3]) -> [[i32; 3]; 3] {
-  let mut result = [[0; 3]; 3];
-  for i in 0..result[0].len() {
-    for j in 0..result[0].len() {
-      result[j][i] = matrix[i][j];
-    }
-  }
-  return result;
-}
-
-fn pretty_print<T, Line, Matrix>(matrix: Matrix)
+fn pretty_print<T, Line, Lines>(matrix: Lines)
 where
   // easier to read Generics.
   T: Debug,
   // A line references a slice of items
   Line: AsRef<[T]>,
-  // A matrix references a slice of lines
-  Matrix: AsRef<[Line]>,
+  // A matrix is anything that yields its lines, like `Matrix::row_iter`
+  Lines: IntoIterator<Item = Line>,
 {
-  for row in matrix.as_ref() {
+  for row in matrix {
     println!("{:?}", row.as_ref())
   }
 }
 
 #[test]
 fn test_transpose() {
-  let matrix = [
+  let matrix = Matrix::from([
     [101, 102, 103], //
     [201, 202, 203],
     [301, 302, 303],
-  ];
-  let transposed = transpose(&matrix);
+  ]);
+  let transposed = matrix.transpose();
 
   assert_eq!(
     transposed,
-    [
+    Matrix::from([
       [101, 201, 301], //
       [102, 202, 302],
       [103, 203, 303],
-    ]
+    ])
   )
 }
 
@@ -7,3 +7,6 @@ pub mod tests;
 pub mod tools;
 pub mod traits;
 pub mod unsafes;
+
+// Built by the library, which the binaries share.
+pub use rust_wasm::matrix;
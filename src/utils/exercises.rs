@@ -1,7 +1,9 @@
 pub mod array_for_loops;
 pub mod dining_philosophers;
-pub mod multi_threaded_link_checker;
 pub mod polygon_struct;
 pub mod simple_gui_library;
 pub mod storing_book;
 pub mod strings_and_iterators;
+
+// Built by the library, which the binaries share.
+pub use rust_wasm::link_checker as multi_threaded_link_checker;
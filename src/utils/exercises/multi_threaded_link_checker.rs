@@ -1,94 +1,654 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
 use std::{sync::Arc, sync::Mutex, sync::mpsc, thread};
 
+pub mod async_crawler;
+mod cache;
+mod checkpoint;
+pub mod cli;
+mod config;
+mod config_file;
+mod extract;
+mod fetch;
+mod fragments;
+mod local_site;
+mod normalize;
+mod progress;
+mod rate_limit;
+mod redirect;
+mod report;
+mod retry;
+mod robots;
+mod scope;
+mod sitemap;
+// Also serves the crawls in `tests/`, which only see the public API.
+#[doc(hidden)]
+pub mod test_server;
+
+use cache::{CacheConfig, ResponseCache};
+use checkpoint::{Checkpoint, CheckpointConfig};
+use config::{CrawlConfig, SkipReason};
+use extract::{extract_links, ResourceKind};
+use fetch::{Fetcher, HttpFetcher};
+use progress::{CancelToken, Progress, ProgressEvent};
+use rate_limit::HostRateLimiter;
+use redirect::Redirect;
+use report::{CheckedUrl, CrawlReport};
+use robots::{RobotsPolicy, Verdict};
+
 // ANCHOR: setup
-use reqwest::{blocking::Client, Url};
-use scraper::{Html, Selector};
+use reqwest::{header, StatusCode, Url};
+use scraper::Html;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 enum Error {
     #[error("request error: {0}")]
-    ReqwestError(#[from] reqwest::Error),
-    #[error("bad http response: {0}")]
-    BadResponse(String),
+    Reqwest(#[from] reqwest::Error),
+    #[error("bad http response: {status}")]
+    BadResponse { status: StatusCode, retry_after: Option<Duration> },
+    #[error("disallowed by robots.txt")]
+    DisallowedByRobots,
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("no element with id or name {0:?}")]
+    MissingFragment(String),
+    #[error("too many redirects, gave up at {0}")]
+    TooManyRedirects(Url),
+    #[error("redirect loop back to {0}")]
+    RedirectLoop(Url),
+    #[error("redirect from https to insecure {0}")]
+    InsecureRedirect(Url),
+    #[error("invalid sitemap: {0}")]
+    InvalidSitemap(String),
+    #[error("no connection within {0:?}")]
+    ConnectTimeout(Duration),
+    #[error("no complete response within {0:?}")]
+    ReadTimeout(Duration),
+    #[error("body larger than {0} bytes")]
+    BodyTooLarge(u64),
+    /// A failure read back from a checkpoint. Only its description survives.
+    #[error("{message}")]
+    Restored { kind: FailureKind, status: Option<StatusCode>, message: String },
 }
 // ANCHOR_END: setup
 
+/// How a URL failed. Derived from the `Error` variant so the report can
+/// tell an HTTP error page from a host that could not be reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FailureKind {
+    HttpStatus,
+    Connect,
+    Timeout,
+    BodyTooLarge,
+    MissingFragment,
+    Redirect,
+    Other,
+}
+
+impl Error {
+    fn kind(&self) -> FailureKind {
+        match self {
+            Error::BadResponse { .. } => FailureKind::HttpStatus,
+            Error::Reqwest(error) if error.is_timeout() => FailureKind::Timeout,
+            // DNS lookup failures surface as connect errors too.
+            Error::Reqwest(error) if error.is_connect() => FailureKind::Connect,
+            Error::Reqwest(error) if error.is_status() => FailureKind::HttpStatus,
+            Error::ConnectTimeout(_) | Error::ReadTimeout(_) => FailureKind::Timeout,
+            Error::BodyTooLarge(_) => FailureKind::BodyTooLarge,
+            Error::MissingFragment(_) => FailureKind::MissingFragment,
+            Error::Restored { kind, .. } => *kind,
+            Error::TooManyRedirects(_) | Error::RedirectLoop(_) | Error::InsecureRedirect(_) => {
+                FailureKind::Redirect
+            }
+            Error::Reqwest(_) | Error::DisallowedByRobots | Error::Io(_) | Error::InvalidSitemap(_) => {
+                FailureKind::Other
+            }
+        }
+    }
+
+    /// The HTTP status behind the error, if the server answered at all.
+    fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::BadResponse { status, .. } => Some(*status),
+            Error::Reqwest(error) => error.status(),
+            Error::Restored { status, .. } => *status,
+            Error::DisallowedByRobots
+            | Error::Io(_)
+            | Error::MissingFragment(_)
+            | Error::TooManyRedirects(_)
+            | Error::RedirectLoop(_)
+            | Error::InsecureRedirect(_)
+            | Error::InvalidSitemap(_)
+            | Error::ConnectTimeout(_)
+            | Error::ReadTimeout(_)
+            | Error::BodyTooLarge(_) => None,
+        }
+    }
+
+    fn bad_response(status: StatusCode, headers: &header::HeaderMap) -> Error {
+        let retry_after = headers
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| retry::parse_retry_after(value, SystemTime::now()));
+        Error::BadResponse { status, retry_after }
+    }
+
+    /// Whether another attempt might succeed: overloaded or failing
+    /// servers, timeouts and dropped connections.
+    fn is_retryable(&self) -> bool {
+        match self {
+            Error::BadResponse { status, .. } => matches!(
+                *status,
+                StatusCode::REQUEST_TIMEOUT
+                    | StatusCode::TOO_MANY_REQUESTS
+                    | StatusCode::INTERNAL_SERVER_ERROR
+                    | StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            ),
+            Error::Reqwest(error) => error.is_timeout() || error.is_connect() || error.is_request(),
+            Error::ConnectTimeout(_) | Error::ReadTimeout(_) => true,
+            Error::DisallowedByRobots
+            | Error::Io(_)
+            | Error::MissingFragment(_)
+            | Error::TooManyRedirects(_)
+            | Error::RedirectLoop(_)
+            | Error::InsecureRedirect(_)
+            | Error::InvalidSitemap(_)
+            | Error::BodyTooLarge(_)
+            | Error::Restored { .. } => false,
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::BadResponse { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl FailureKind {
+    /// A stable identifier for machine-readable reports.
+    fn id(&self) -> &'static str {
+        match self {
+            FailureKind::HttpStatus => "http_status",
+            FailureKind::Connect => "connect",
+            FailureKind::Timeout => "timeout",
+            FailureKind::BodyTooLarge => "body_too_large",
+            FailureKind::MissingFragment => "missing_fragment",
+            FailureKind::Redirect => "redirect",
+            FailureKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FailureKind::HttpStatus => "HTTP status",
+            FailureKind::Connect => "DNS/connect error",
+            FailureKind::Timeout => "timeout",
+            FailureKind::BodyTooLarge => "body too large",
+            FailureKind::MissingFragment => "missing fragment",
+            FailureKind::Redirect => "bad redirect",
+            FailureKind::Other => "other error",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Sent with every request and matched against robots.txt groups.
+const USER_AGENT: &str = "rust-wasm-link-checker";
+
 // ANCHOR: visit_page
-#[derive(Debug)]
+/// Where a link was found: the page it is on and the element holding it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct LinkSource {
+    page: Url,
+    kind: ResourceKind,
+    element: String,
+    text: String,
+}
+
+/// A link found on a page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Link {
+    url: Url,
+    source: LinkSource,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct CrawlCommand {
+    /// The URL to fetch, always without a fragment.
     url: Url,
     extract_links: bool,
+    /// Whether a link into this page has a fragment, so its anchors are
+    /// needed even if its links are not.
+    collect_anchors: bool,
+    /// Number of links followed from the start URL to get here.
+    depth: usize,
+    /// The link that led here, `None` for the start URL.
+    source: Option<LinkSource>,
+    /// Whether the URL is on another host than the start URL.
+    external: bool,
 }
 
-fn visit_page(client: &Client, command: &CrawlCommand) -> Result<Vec<Url>, Error> {
-    println!("Checking {:#}", command.url);
-    let response = client.get(command.url.clone()).send()?;
-    if !response.status().is_success() {
-        return Err(Error::BadResponse(response.status().to_string()));
+/// A successful fetch of one URL.
+#[derive(Debug)]
+struct Visit {
+    status: StatusCode,
+    /// Every redirect followed on the way, in order.
+    redirect_chain: Vec<Redirect>,
+    links: Vec<Link>,
+    /// The ids and names in the page, if its body was parsed.
+    anchors: Option<HashSet<String>>,
+}
+
+/// Whether the response is a page worth parsing. Anything else, an image
+/// or a PDF, is only checked for its status.
+fn is_html(headers: &header::HeaderMap) -> bool {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()) else {
+        return false;
+    };
+    let media_type = content_type.split(';').next().unwrap_or_default().trim();
+    media_type.eq_ignore_ascii_case("text/html") || media_type.eq_ignore_ascii_case("application/xhtml+xml")
+}
+
+/// Follow `command.url` to its final response. Leaf links, whose body is
+/// not needed, are asked with HEAD first; servers that refuse or mishandle
+/// HEAD get a GET instead.
+/// Whether the answer to a leaf link's HEAD request settles it, so no GET is
+/// needed: a success, or a 304 to a conditional request for a cached page.
+/// Both crawlers use this rule.
+fn head_is_enough(status: StatusCode) -> bool {
+    status.is_success() || status == StatusCode::NOT_MODIFIED
+}
+
+fn fetch_page(
+    fetcher: &dyn Fetcher,
+    command: &CrawlCommand,
+    headers: &header::HeaderMap,
+    config: &CrawlConfig,
+) -> Result<(fetch::Response, Vec<Redirect>), Error> {
+    if !command.extract_links && !command.collect_anchors {
+        let (response, redirect_chain) = redirect::follow_head(fetcher, &command.url, headers, &config.redirects)?;
+        if head_is_enough(response.status) {
+            return Ok((response, redirect_chain));
+        }
     }
+    redirect::follow_with_headers(fetcher, &command.url, headers, &config.redirects)
+}
 
-    let mut link_urls = Vec::new();
-    if !command.extract_links {
-        return Ok(link_urls);
+fn visit_page(
+    fetcher: &dyn Fetcher,
+    command: &CrawlCommand,
+    config: &CrawlConfig,
+    cache: Option<&ResponseCache>,
+) -> Result<Visit, Error> {
+    eprintln!("Checking {:#}", command.url);
+    let conditional = cache.map(|cache| cache.conditional_headers(command)).unwrap_or_default();
+    let (response, redirect_chain) = fetch_page(fetcher, command, &conditional, config)?;
+    let status = response.status;
+    if status == StatusCode::NOT_MODIFIED {
+        if let Some(visit) = cache.and_then(|cache| cache.not_modified(command)) {
+            return Ok(visit);
+        }
+    }
+    if !status.is_success() {
+        if let Some(cache) = cache {
+            cache.remove(command);
+        }
+        if config.rules.accepts(&command.url, status) {
+            // Works as far as the config is concerned, but there is no page
+            // to read, e.g. a login wall.
+            return Ok(Visit { status, redirect_chain, links: Vec::new(), anchors: None });
+        }
+        return Err(Error::bad_response(status, &response.headers));
     }
 
-    let base_url = response.url().to_owned();
-    let body_text = response.text()?;
-    let document = Html::parse_document(&body_text);
+    let headers = response.headers.clone();
+    let visit = read_page(response, redirect_chain, command, config)?;
+    if let Some(cache) = cache {
+        cache.store(command, &headers, &visit);
+    }
+    Ok(visit)
+}
 
-    let selector = Selector::parse("a").unwrap();
-    let href_values = document
-        .select(&selector)
-        .filter_map(|element| element.value().attr("href"));
-    for href in href_values {
-        match base_url.join(href) {
-            Ok(link_url) => {
-                link_urls.push(link_url);
-            }
-            Err(err) => {
-                println!("On {base_url:#}: ignored unparsable {href:?}: {err}");
-            }
-        }
+/// Parse what `command` asks for out of a successful response.
+fn read_page(
+    response: fetch::Response,
+    redirect_chain: Vec<Redirect>,
+    command: &CrawlCommand,
+    config: &CrawlConfig,
+) -> Result<Visit, Error> {
+    let status = response.status;
+    let base_url = response.url.clone();
+
+    let mut visit = Visit { status, redirect_chain, links: Vec::new(), anchors: None };
+    if !wants_body(command, &response.headers) {
+        return Ok(visit);
     }
-    Ok(link_urls)
+
+    let body_text = response.text(config.limits.max_body_size)?;
+    parse_body(&mut visit, &base_url, &body_text, command, config);
+    Ok(visit)
 }
+
+/// Whether `command` needs the body of a response with these headers: only
+/// pages are parsed, and only for their links or anchors.
+fn wants_body(command: &CrawlCommand, headers: &header::HeaderMap) -> bool {
+    (command.extract_links || command.collect_anchors) && is_html(headers)
+}
+
+/// Fill in the links and anchors `command` asks for from a page's body.
+fn parse_body(visit: &mut Visit, base_url: &Url, body_text: &str, command: &CrawlCommand, config: &CrawlConfig) {
+    let document = Html::parse_document(body_text);
+    if command.extract_links {
+        visit.links = extract_links(base_url, &document, &config.extractors);
+    }
+    visit.anchors = Some(fragments::anchors(&document));
+}
+
 // ANCHOR_END: visit_page
 
+/// Visit a page, waiting for the host's rate limit before every attempt and
+/// retrying transient failures as the config allows.
+fn visit_with_retries(
+    fetcher: &dyn Fetcher,
+    command: &CrawlCommand,
+    config: &CrawlConfig,
+    rate_limiter: &HostRateLimiter,
+    cache: Option<&ResponseCache>,
+) -> Result<Visit, Error> {
+    let mut attempt = 0;
+    loop {
+        if fetcher.is_remote(&command.url) {
+            rate_limiter.acquire(&command.url);
+        }
+        let error = match visit_page(fetcher, command, config, cache) {
+            Ok(visit) => return Ok(visit),
+            Err(error) if error.is_retryable() => error,
+            Err(error) => return Err(error),
+        };
+        attempt += 1;
+        let Some(delay) = config.retry.delay(attempt, error.retry_after()) else {
+            return Err(error);
+        };
+        eprintln!("Retrying {:#} in {:?} after: {:#}", command.url, delay, error);
+        thread::sleep(delay);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 struct CrawlState {
-    domain: String,
-    visited_pages: std::collections::HashSet<String>,
+    /// Where the crawl started, without fragments. A URL is in scope if it
+    /// is in the scope of any of them.
+    start_urls: Vec<Url>,
+    /// Not saved in checkpoints; a resumed crawl is given it again.
+    #[serde(skip)]
+    config: Arc<CrawlConfig>,
+    /// Normalised form of every URL handed to the workers. The other maps
+    /// are keyed the same way.
+    visited_pages: HashSet<String>,
+    /// Every place each URL was linked from, not only the first one.
+    link_sources: HashMap<String, Vec<LinkSource>>,
+    pages_per_host: HashMap<String, usize>,
+    /// URLs found but held back by the config.
+    skipped: HashMap<String, (Url, SkipReason)>,
+    /// Anchors of every parsed page.
+    anchors: HashMap<String, HashSet<String>>,
+    /// Links with a fragment and where they were found, keyed by the page's
+    /// key plus the fragment. They are checked once every page has been
+    /// fetched.
+    fragment_links: BTreeMap<String, (Url, Vec<LinkSource>)>,
+    /// Links that are not http(s), such as `mailto:`. Never fetched.
+    non_http_links: BTreeSet<Url>,
 }
 
 impl CrawlState {
-    fn new(start_url: &Url) -> CrawlState {
-        let mut visited_pages = std::collections::HashSet::new();
-        visited_pages.insert(start_url.as_str().to_string());
+    fn new(start_urls: &[Url], config: Arc<CrawlConfig>) -> CrawlState {
+        let start_urls = start_urls
+            .iter()
+            .map(|start_url| {
+                let mut start_url = start_url.clone();
+                start_url.set_fragment(None);
+                start_url
+            })
+            .collect();
         CrawlState {
-            domain: start_url.domain().unwrap().to_string(),
-            visited_pages,
+            start_urls,
+            config,
+            visited_pages: HashSet::new(),
+            link_sources: HashMap::new(),
+            pages_per_host: HashMap::new(),
+            skipped: HashMap::new(),
+            anchors: HashMap::new(),
+            fragment_links: BTreeMap::new(),
+            non_http_links: BTreeSet::new(),
+        }
+    }
+
+    /// The first commands of a crawl, one per start URL. Start URLs are
+    /// never skipped.
+    fn start_commands(&mut self) -> Vec<CrawlCommand> {
+        let mut crawl_commands = Vec::new();
+        for start_url in self.start_urls.clone() {
+            if !self.visited_pages.contains(&self.key(&start_url)) {
+                crawl_commands.push(self.enqueue(start_url, 0, None, false));
+            }
         }
+        crawl_commands
+    }
+
+    /// Whether `url` belongs to the site being crawled.
+    fn in_scope(&self, url: &Url) -> bool {
+        self.start_urls.iter().any(|start_url| self.config.scope.contains(start_url, url))
     }
 
     /// Determine whether links within the given page should be extracted.
     fn should_extract_links(&self, url: &Url) -> bool {
-        let Some(url_domain) = url.domain() else {
-            return false;
-        };
-        url_domain == self.domain
+        self.in_scope(url)
+    }
+
+    /// The key `url` is deduplicated on: every spelling of the same page
+    /// gets the same one.
+    fn key(&self, url: &Url) -> String {
+        self.config.normalize.normalize(url).into()
+    }
+
+    /// Mark the given page as visited.
+    fn mark_visited(&mut self, url: &Url) {
+        let key = self.key(url);
+        self.visited_pages.insert(key);
+    }
+
+    /// Check a newly found URL against the config's rules and budgets.
+    fn skip_reason(&self, url: &Url) -> Option<SkipReason> {
+        if !self.config.is_in_scope(url) {
+            return Some(SkipReason::Excluded);
+        }
+        if !self.config.check_external && !self.in_scope(url) {
+            return Some(SkipReason::External);
+        }
+        if let Some(max_pages) = self.config.max_pages {
+            if self.visited_pages.len() >= max_pages {
+                return Some(SkipReason::MaxPages);
+            }
+        }
+        if let Some(max_pages_per_host) = self.config.max_pages_per_host {
+            let host = url.host_str().unwrap_or_default();
+            if self.pages_per_host.get(host).copied().unwrap_or(0) >= max_pages_per_host {
+                return Some(SkipReason::MaxPagesPerHost);
+            }
+        }
+        None
+    }
+
+    fn enqueue(
+        &mut self,
+        url: Url,
+        depth: usize,
+        source: Option<LinkSource>,
+        has_fragment: bool,
+    ) -> CrawlCommand {
+        self.mark_visited(&url);
+        self.skipped.remove(&self.key(&url));
+        let host = url.host_str().unwrap_or_default().to_string();
+        *self.pages_per_host.entry(host).or_default() += 1;
+        // Links on a page at the maximum depth would be too deep to follow,
+        // and images, scripts or stylesheets have no links to follow at all.
+        let below_max_depth = self.config.max_depth.is_none_or(|max_depth| depth < max_depth);
+        let is_document = source.as_ref().is_none_or(|source| source.kind.is_document());
+        let extract_links = below_max_depth && is_document && self.should_extract_links(&url);
+        let collect_anchors = extract_links || has_fragment;
+        let external = !self.in_scope(&url);
+        CrawlCommand { url, extract_links, collect_anchors, depth, source, external }
+    }
+
+    /// Turn the links found on a page at `depth` into commands for the
+    /// pages that have not been visited yet and are within the limits.
+    fn new_commands(&mut self, depth: usize, links: Vec<Link>) -> Vec<CrawlCommand> {
+        self.commands_at(depth + 1, links)
+    }
+
+    /// Commands for the pages listed in sitemaps, which are as close to
+    /// the start as the start URL itself.
+    fn sitemap_commands(&mut self, links: Vec<Link>) -> Vec<CrawlCommand> {
+        self.commands_at(0, links)
+    }
+
+    fn commands_at(&mut self, depth: usize, links: Vec<Link>) -> Vec<CrawlCommand> {
+        let mut crawl_commands = Vec::new();
+        for Link { mut url, source } in links {
+            if !matches!(url.scheme(), "http" | "https") {
+                self.non_http_links.insert(url);
+                continue;
+            }
+            // Pages are fetched once however many fragments point into them.
+            let key = self.key(&url);
+            let has_fragment = url.fragment().is_some_and(|fragment| !fragment.is_empty());
+            if has_fragment {
+                let fragment_key = format!("{key}#{}", url.fragment().unwrap_or_default());
+                let (_, sources) = self
+                    .fragment_links
+                    .entry(fragment_key)
+                    .or_insert_with(|| (url.clone(), Vec::new()));
+                sources.push(source.clone());
+            }
+            url.set_fragment(None);
+
+            self.link_sources.entry(key.clone()).or_default().push(source.clone());
+            if self.visited_pages.contains(&key) {
+                continue;
+            }
+            if let Some(reason) = self.skip_reason(&url) {
+                if !self.skipped.contains_key(&key) {
+                    self.config.progress.emit(ProgressEvent::Skipped { url: url.clone(), reason });
+                    self.skipped.insert(key, (url, reason));
+                }
+                continue;
+            }
+            crawl_commands.push(self.enqueue(url, depth, Some(source), has_fragment));
+        }
+        crawl_commands
+    }
+
+    fn record_anchors(&mut self, url: &Url, anchors: HashSet<String>) {
+        let key = self.key(url);
+        self.anchors.insert(key, anchors);
+    }
+
+    /// Links whose fragment is not an anchor of the target page. Pages that
+    /// failed, were skipped or were not parsed cannot be checked and are
+    /// left out.
+    fn take_missing_fragments(&mut self) -> Vec<(Url, Vec<LinkSource>)> {
+        let mut missing = Vec::new();
+        for (_, (url, sources)) in std::mem::take(&mut self.fragment_links) {
+            let Some(anchors) = self.anchors.get(&self.key(&url)) else {
+                continue;
+            };
+            if !fragments::has_anchor(anchors, url.fragment().unwrap_or_default()) {
+                missing.push((url, sources));
+            }
+        }
+        missing
+    }
+
+    /// Links with other schemes than http(s), in order.
+    fn take_non_http_links(&mut self) -> Vec<Url> {
+        std::mem::take(&mut self.non_http_links).into_iter().collect()
+    }
+
+    /// URLs that were found but never checked, with the reason why.
+    fn take_skipped(&mut self) -> Vec<(Url, SkipReason)> {
+        let mut skipped: Vec<_> = self.skipped.drain().map(|(_, skipped)| skipped).collect();
+        skipped.sort_by(|(a, _), (b, _)| a.cmp(b));
+        skipped
+    }
+
+    /// Whether `checked` was only reached through sitemaps. Call once its
+    /// sources are filled in.
+    fn is_orphan(&self, checked: &CheckedUrl) -> bool {
+        !checked.sources.is_empty()
+            && checked.sources.iter().all(|source| source.kind == ResourceKind::SitemapEntry)
+            && !self.start_urls.iter().any(|start_url| self.key(start_url) == self.key(&checked.url))
+    }
+
+    /// Take the recorded sources of `url`, used once the crawl is over.
+    fn take_sources(&mut self, url: &Url) -> Vec<LinkSource> {
+        let key = self.key(url);
+        self.link_sources.remove(&key).unwrap_or_default()
+    }
+}
+
+/// What a worker sends back for every command it was given.
+#[derive(Debug)]
+struct CrawlResult {
+    command: CrawlCommand,
+    elapsed: Duration,
+    /// `None` if the crawl was cancelled before the command was started.
+    visit: Option<Result<Visit, Error>>,
+}
+
+/// The per-host rules every remote request obeys, shared by the workers and
+/// the sitemap reader.
+struct HostPolicies {
+    robots: RobotsPolicy,
+    rate_limiter: HostRateLimiter,
+}
+
+impl HostPolicies {
+    fn new(config: &CrawlConfig) -> HostPolicies {
+        HostPolicies { robots: RobotsPolicy::new(), rate_limiter: HostRateLimiter::new(config.rate_limit) }
     }
 
-    /// Mark the given page as visited, returning true if it had already
-    /// been visited.
-    fn mark_visited(&mut self, url: &Url) -> bool {
-        self.visited_pages.insert(url.as_str().to_string())
+    /// Check `url` against robots.txt and wait for its host's rate limit, for
+    /// a request that is made once rather than retried.
+    fn admit(&self, fetcher: &dyn Fetcher, url: &Url) -> Result<(), Error> {
+        if !fetcher.is_remote(url) {
+            return Ok(());
+        }
+        if let Verdict::Disallowed = self.robots.check(fetcher, url) {
+            return Err(Error::DisallowedByRobots);
+        }
+        self.rate_limiter.acquire(url);
+        Ok(())
     }
 }
 
-type CrawlResult = Result<Vec<Url>, (Url, Error)>;
 fn spawn_crawler_threads(
     command_receiver: mpsc::Receiver<CrawlCommand>,
     result_sender: mpsc::Sender<CrawlResult>,
+    config: Arc<CrawlConfig>,
+    fetcher: Arc<dyn Fetcher>,
+    policies: Arc<HostPolicies>,
+    cache: Option<Arc<ResponseCache>>,
     thread_count: u32,
 ) {
     let command_receiver = Arc::new(Mutex::new(command_receiver));
@@ -96,8 +656,11 @@ fn spawn_crawler_threads(
     for _ in 0..thread_count {
         let result_sender = result_sender.clone();
         let command_receiver = command_receiver.clone();
+        let policies = policies.clone();
+        let config = config.clone();
+        let fetcher = fetcher.clone();
+        let cache = cache.clone();
         thread::spawn(move || {
-            let client = Client::new();
             loop {
                 let command_result = {
                     let receiver_guard = command_receiver.lock().unwrap();
@@ -107,61 +670,874 @@ fn spawn_crawler_threads(
                     // The sender got dropped. No more commands coming in.
                     break;
                 };
-                let crawl_result = match visit_page(&client, &crawl_command) {
-                    Ok(link_urls) => Ok(link_urls),
-                    Err(error) => Err((crawl_command.url, error)),
+                if config.cancel.is_cancelled() {
+                    let crawl_result = CrawlResult { command: crawl_command, elapsed: Duration::ZERO, visit: None };
+                    if result_sender.send(crawl_result).is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                config.progress.emit(ProgressEvent::Started { url: crawl_command.url.clone() });
+                let started = Instant::now();
+                let cache = cache.as_deref();
+                // A recently checked external link is not worth a request,
+                // not even for robots.txt.
+                let visit = if let Some(visit) = cache.and_then(|cache| cache.fresh(&crawl_command)) {
+                    Ok(visit)
+                } else {
+                    let verdict = if fetcher.is_remote(&crawl_command.url) {
+                        policies.robots.check(fetcher.as_ref(), &crawl_command.url)
+                    } else {
+                        Verdict::Allowed
+                    };
+                    match verdict {
+                        Verdict::Disallowed => Err(Error::DisallowedByRobots),
+                        Verdict::Allowed => {
+                            visit_with_retries(fetcher.as_ref(), &crawl_command, &config, &policies.rate_limiter, cache)
+                        }
+                    }
                 };
-                result_sender.send(crawl_result).unwrap();
+                let crawl_result = CrawlResult {
+                    command: crawl_command,
+                    elapsed: started.elapsed(),
+                    visit: Some(visit),
+                };
+                if result_sender.send(crawl_result).is_err() {
+                    // The crawl was stopped and nobody is listening.
+                    break;
+                }
             }
         });
     }
 }
 
+/// Save a checkpoint if the config asks for them, reporting but otherwise
+/// ignoring failures: a crawl is not worth aborting over a full disk.
+fn save_checkpoint(
+    config: &CrawlConfig,
+    crawl_state: &CrawlState,
+    in_flight: &BTreeMap<String, CrawlCommand>,
+    report: &CrawlReport,
+) {
+    let Some(checkpoint) = &config.checkpoint else {
+        return;
+    };
+    if let Err(error) = Checkpoint::save(&checkpoint.path, crawl_state, in_flight.values(), report) {
+        eprintln!("Could not write checkpoint {}: {}", checkpoint.path.display(), error);
+    }
+}
+
 fn control_crawl(
-    start_url: Url,
+    mut crawl_state: CrawlState,
+    frontier: Vec<CrawlCommand>,
+    mut report: CrawlReport,
     command_sender: mpsc::Sender<CrawlCommand>,
     result_receiver: mpsc::Receiver<CrawlResult>,
-) -> Vec<Url> {
-    let mut crawl_state = CrawlState::new(&start_url);
-    let start_command = CrawlCommand { url: start_url, extract_links: true };
-    command_sender.send(start_command).unwrap();
-    let mut pending_urls = 1;
-
-    let mut bad_urls = Vec::new();
-    while pending_urls > 0 {
-        let crawl_result = result_receiver.recv().unwrap();
-        pending_urls -= 1;
-
-        match crawl_result {
-            Ok(link_urls) => {
-                for url in link_urls {
-                    if crawl_state.mark_visited(&url) {
-                        let extract_links = crawl_state.should_extract_links(&url);
-                        let crawl_command = CrawlCommand { url, extract_links };
-                        command_sender.send(crawl_command).unwrap();
-                        pending_urls += 1;
-                    }
+) -> CrawlReport {
+    let config = crawl_state.config.clone();
+    // Commands without a result yet, which is exactly what a resumed crawl
+    // has to send again.
+    let mut in_flight = BTreeMap::new();
+    // How many of those the workers have. Once the crawl is cancelled, new
+    // commands are only recorded, and the crawl ends when this reaches zero.
+    let mut unanswered = 0;
+    let send = |crawl_command: CrawlCommand, in_flight: &mut BTreeMap<String, CrawlCommand>, unanswered: &mut usize| {
+        in_flight.insert(crawl_command.url.as_str().to_string(), crawl_command.clone());
+        if !config.cancel.is_cancelled() {
+            config.progress.emit(ProgressEvent::Queued { url: crawl_command.url.clone(), depth: crawl_command.depth });
+            command_sender.send(crawl_command).unwrap();
+            *unanswered += 1;
+        }
+    };
+    for crawl_command in frontier {
+        send(crawl_command, &mut in_flight, &mut unanswered);
+    }
+
+    let mut last_checkpoint = Instant::now();
+    while unanswered > 0 {
+        if let Some(checkpoint) = &config.checkpoint {
+            if !config.cancel.is_cancelled() && last_checkpoint.elapsed() >= checkpoint.interval {
+                save_checkpoint(&config, &crawl_state, &in_flight, &report);
+                last_checkpoint = Instant::now();
+            }
+        }
+        // Wake up now and then to write checkpoints.
+        let CrawlResult { command, elapsed, visit } = match result_receiver.recv_timeout(Duration::from_millis(100)) {
+            Ok(crawl_result) => crawl_result,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => panic!("all crawler threads exited"),
+        };
+        unanswered -= 1;
+        let Some(visit) = visit else {
+            // Cancelled before it started; it stays in flight.
+            continue;
+        };
+        in_flight.remove(command.url.as_str());
+
+        match visit {
+            Ok(visit) => {
+                config.progress.emit(ProgressEvent::Finished {
+                    url: command.url.clone(),
+                    status: Some(visit.status),
+                    elapsed,
+                    error: None,
+                });
+                if let Some(anchors) = visit.anchors {
+                    crawl_state.record_anchors(&command.url, anchors);
                 }
+                report.checked_urls.push(CheckedUrl {
+                    url: command.url,
+                    status: Some(visit.status),
+                    redirect_chain: visit.redirect_chain,
+                    elapsed,
+                    sources: Vec::new(),
+                    error: None,
+                });
+                for crawl_command in crawl_state.new_commands(command.depth, visit.links) {
+                    send(crawl_command, &mut in_flight, &mut unanswered);
+                }
+            }
+            Err(Error::DisallowedByRobots) => {
+                eprintln!("Skipping {:#}: disallowed by robots.txt", command.url);
+                config.progress.emit(ProgressEvent::Disallowed { url: command.url.clone() });
+                report.disallowed_urls.push(command.url);
             }
-            Err((url, error)) => {
-                bad_urls.push(url);
-                println!("Got crawling error: {:#}", error);
+            Err(error) => {
+                match &command.source {
+                    Some(source) => eprintln!(
+                        "Got crawling error on {:#} (linked from {:#}): {:#}",
+                        command.url, source.page, error
+                    ),
+                    None => eprintln!("Got crawling error: {:#}", error),
+                }
+                config.progress.emit(ProgressEvent::Finished {
+                    url: command.url.clone(),
+                    status: error.status(),
+                    elapsed,
+                    error: Some(error.kind()),
+                });
+                report.checked_urls.push(CheckedUrl {
+                    url: command.url,
+                    status: error.status(),
+                    redirect_chain: Vec::new(),
+                    elapsed,
+                    sources: Vec::new(),
+                    error: Some(error),
+                });
                 continue;
             }
         }
     }
-    bad_urls
+
+    if !in_flight.is_empty() {
+        // Only a cancelled crawl leaves commands behind.
+        report.interrupted = true;
+        save_checkpoint(&config, &crawl_state, &in_flight, &report);
+        match &config.checkpoint {
+            Some(checkpoint) => eprintln!("Crawl cancelled, checkpoint written to {}", checkpoint.path.display()),
+            None => eprintln!("Crawl cancelled"),
+        }
+    }
+
+    // Only now is every page linking to a URL known.
+    for checked_url in &mut report.checked_urls {
+        checked_url.sources = crawl_state.take_sources(&checked_url.url);
+    }
+    if report.interrupted {
+        // Fragments and skipped URLs are only settled by a complete crawl.
+        return report;
+    }
+    for (url, sources) in crawl_state.take_missing_fragments() {
+        let fragment = url.fragment().unwrap_or_default().to_string();
+        report.checked_urls.push(CheckedUrl {
+            url,
+            status: None,
+            redirect_chain: Vec::new(),
+            elapsed: Duration::ZERO,
+            sources,
+            error: Some(Error::MissingFragment(fragment)),
+        });
+    }
+    report.skipped_urls = crawl_state.take_skipped();
+    report.non_http_urls = crawl_state.take_non_http_links();
+    report.orphan_urls = report
+        .checked_urls
+        .iter()
+        .filter(|checked| checked.error.is_none() && crawl_state.is_orphan(checked))
+        .map(|checked| checked.url.clone())
+        .collect();
+    report
+}
+
+fn check_links(start_url: Url, config: CrawlConfig) -> Result<CrawlReport, reqwest::Error> {
+    check_sites(&[start_url], config)
+}
+
+/// Crawl from several start URLs at once, as one site. Fails only if the
+/// HTTP client cannot be set up.
+fn check_sites(start_urls: &[Url], config: CrawlConfig) -> Result<CrawlReport, reqwest::Error> {
+    let fetcher = Arc::new(HttpFetcher::new(&config.limits, &config.rules)?);
+    Ok(check_sites_with(start_urls, config, fetcher))
+}
+
+fn check_links_with(start_url: Url, config: CrawlConfig, fetcher: Arc<dyn Fetcher>) -> CrawlReport {
+    check_sites_with(&[start_url], config, fetcher)
+}
+
+/// Crawl from `start_urls` and the pages in the configured sitemaps, making
+/// every request through `fetcher`.
+fn check_sites_with(start_urls: &[Url], config: CrawlConfig, fetcher: Arc<dyn Fetcher>) -> CrawlReport {
+    let config = Arc::new(config);
+    let mut crawl_state = CrawlState::new(start_urls, config.clone());
+    let mut frontier = crawl_state.start_commands();
+    let mut report = CrawlReport::default();
+    let policies = Arc::new(HostPolicies::new(&config));
+    if !config.sitemaps.is_empty() {
+        let sitemaps = sitemap::read_sitemaps(fetcher.as_ref(), &config.sitemaps, &config.redirects, &policies);
+        frontier.extend(crawl_state.sitemap_commands(sitemaps.links));
+        report.checked_urls.extend(sitemaps.failures);
+        report.disallowed_urls.extend(sitemaps.disallowed);
+    }
+    run_crawl(crawl_state, frontier, report, fetcher, policies)
+}
+
+/// Continue the crawl saved in `checkpoint`. `config` should be the one the
+/// crawl was started with, as it is not part of the checkpoint.
+fn resume_links_with(checkpoint: Checkpoint, config: CrawlConfig, fetcher: Arc<dyn Fetcher>) -> CrawlReport {
+    let policies = Arc::new(HostPolicies::new(&config));
+    let (crawl_state, frontier, report) = checkpoint.restore(Arc::new(config));
+    run_crawl(crawl_state, frontier, report, fetcher, policies)
 }
 
-fn check_links(start_url: Url) -> Vec<Url> {
+fn run_crawl(
+    crawl_state: CrawlState,
+    frontier: Vec<CrawlCommand>,
+    report: CrawlReport,
+    fetcher: Arc<dyn Fetcher>,
+    policies: Arc<HostPolicies>,
+) -> CrawlReport {
     let (result_sender, result_receiver) = mpsc::channel::<CrawlResult>();
     let (command_sender, command_receiver) = mpsc::channel::<CrawlCommand>();
-    spawn_crawler_threads(command_receiver, result_sender, 16);
-    control_crawl(start_url, command_sender, result_receiver)
+    let config = crawl_state.config.clone();
+    let cache = config.cache.clone().and_then(|cache_config| {
+        let path = cache_config.path.clone();
+        match ResponseCache::load(cache_config) {
+            Ok(cache) => Some(Arc::new(cache)),
+            Err(error) => {
+                eprintln!("Could not read cache {}, not using it: {}", path.display(), error);
+                None
+            }
+        }
+    });
+    let thread_count = config.threads;
+    spawn_crawler_threads(command_receiver, result_sender, config, fetcher, policies, cache.clone(), thread_count);
+    let report = control_crawl(crawl_state, frontier, report, command_sender, result_receiver);
+    if let Some(cache) = cache {
+        if let Err(error) = cache.save() {
+            eprintln!("Could not write cache {}: {}", cache.path().display(), error);
+        }
+    }
+    report
 }
 
-pub fn link_checker() {
+/// Where `link_checker` and `async_link_checker` read their settings from,
+/// if the file exists.
+pub const CONFIG_FILE: &str = "link-checker.toml";
+
+/// Crawl a sample site with the settings in `CONFIG_FILE`, resuming from the checkpoint a cancelled run left
+/// behind. A checkpoint that exists but cannot be read is an error rather
+/// than a reason to start over.
+pub fn link_checker() -> io::Result<()> {
     let start_url = reqwest::Url::parse("https://www.google.org").unwrap();
-    let bad_urls = check_links(start_url);
-    println!("Bad URLs: {:#?}", bad_urls);
-}
\ No newline at end of file
+    let file_config = CrawlConfig::from_file_or_default(Path::new(CONFIG_FILE)).map_err(io::Error::other)?;
+    let checkpoint = CheckpointConfig::new("link-checker-checkpoint.json");
+    let saved = match Checkpoint::load(&checkpoint.path) {
+        Ok(saved) => Some(saved),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => {
+            let message = format!("could not read checkpoint {}: {}", checkpoint.path.display(), error);
+            return Err(io::Error::new(error.kind(), message));
+        }
+    };
+    let cancel = CancelToken::default();
+    if let Err(error) = progress::cancel_on_signal(&cancel) {
+        // Most likely a handler from an earlier run: the crawl just cannot be cancelled.
+        eprintln!("Could not install the Ctrl-C handler: {}", error);
+    }
+    let (progress, events) = Progress::channel();
+    let live_summary = thread::spawn(move || progress::render_live(events));
+    let config = CrawlConfig {
+        max_pages: file_config.max_pages.or(Some(200)),
+        checkpoint: Some(checkpoint.clone()),
+        cache: Some(CacheConfig {
+            path: "link-checker-cache.json".into(),
+            recheck_external_after: Some(Duration::from_secs(24 * 60 * 60)),
+        }),
+        progress,
+        cancel,
+        ..file_config
+    };
+    let report = match saved {
+        Some(saved) => {
+            eprintln!("Resuming from {}", checkpoint.path.display());
+            let fetcher = Arc::new(HttpFetcher::new(&config.limits, &config.rules).map_err(io::Error::other)?);
+            resume_links_with(saved, config, fetcher)
+        }
+        None => check_links(start_url, config).map_err(io::Error::other)?,
+    };
+    // Returns once the last worker has exited and dropped its sender.
+    live_summary.join().unwrap();
+    if report.interrupted {
+        eprintln!("Run again to resume the crawl.");
+        return Ok(());
+    }
+    let _ = std::fs::remove_file(&checkpoint.path);
+    print!("{}", report::to_text(&report));
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::config::{UrlPattern, UrlRule, UrlRules};
+    use super::fetch::{FetchLimits, MemoryFetcher, Response};
+    use super::normalize::NormalizeRules;
+    use super::progress::ProgressStats;
+    use super::retry::RetryPolicy;
+    use super::scope::ScopePolicy;
+    use super::test_server::TestServer;
+    use super::*;
+
+    #[test]
+    fn test_failures_grouped_by_source_page() {
+        let server = TestServer::new()
+            .route("/", 200, r#"<a href="/a">Page A</a> <a href="/missing">Gone <b>page</b></a>"#)
+            .route("/a", 200, r#"<a href="/missing">Also gone</a>"#)
+            .start();
+
+        let report = check_links(server.url("/"), CrawlConfig::default()).unwrap();
+
+        assert_eq!(report.bad_urls(), vec![server.url("/missing")]);
+        let (home, page_a) = (server.url("/"), server.url("/a"));
+        let by_page = report.failures_by_page();
+        assert_eq!(by_page.len(), 2);
+        let on_home = &by_page[&Some(&home)];
+        assert_eq!(on_home.len(), 1);
+        assert_eq!(on_home[0].source.unwrap().text, "Gone page");
+        assert_eq!(on_home[0].error.kind(), FailureKind::HttpStatus);
+        let on_a = &by_page[&Some(&page_a)];
+        assert_eq!(on_a[0].source.unwrap().text, "Also gone");
+    }
+
+    #[test]
+    fn test_broken_resources_are_tagged_by_kind() {
+        let server = TestServer::new()
+            .route(
+                "/",
+                200,
+                r#"<link rel="stylesheet" href="/gone.css"><img src="/logo.png"><a href="/gone">x</a>"#,
+            )
+            .route_with_headers("/logo.png", 200, &[("Content-Type", "image/png")], "<a href=/never>")
+            .start();
+
+        let report = check_links(server.url("/"), CrawlConfig::default()).unwrap();
+
+        let mut broken: Vec<_> = report
+            .failures_by_page()
+            .into_values()
+            .flatten()
+            .map(|broken_link| (broken_link.source.unwrap().kind, broken_link.url.path().to_string()))
+            .collect();
+        broken.sort();
+        assert_eq!(
+            broken,
+            vec![
+                (ResourceKind::Hyperlink, String::from("/gone")),
+                (ResourceKind::Stylesheet, String::from("/gone.css")),
+            ]
+        );
+        // The image is checked but never parsed for links.
+        assert_eq!(report.checked_urls.len(), 4);
+    }
+
+    #[test]
+    fn test_crawl_limits_hold_back_commands() {
+        let server = TestServer::new()
+            .route("/", 200, r#"<a href="/a">a</a> <a href="/legacy/b">b</a>"#)
+            .route("/a", 200, r#"<a href="/a/deeper">deeper</a> <a href="/c">c</a>"#)
+            .start();
+        let config = CrawlConfig {
+            max_depth: Some(1),
+            exclude: vec![UrlPattern::glob("*/legacy/*").unwrap()],
+            ..CrawlConfig::default()
+        };
+
+        let report = check_links(server.url("/"), config).unwrap();
+
+        // `/a` is at the maximum depth: it is checked, its links are not.
+        let checked: Vec<_> = report.checked_urls.iter().map(|checked| checked.url.clone()).collect();
+        assert_eq!(checked.len(), 2);
+        assert!(checked.contains(&server.url("/a")));
+        assert_eq!(report.skipped_urls, vec![(server.url("/legacy/b"), SkipReason::Excluded)]);
+    }
+
+    #[test]
+    fn test_page_budget() {
+        let server = TestServer::new()
+            .route("/", 200, r#"<a href="/a">a</a> <a href="/b">b</a> <a href="/c">c</a>"#)
+            .start();
+        let config = CrawlConfig { max_pages: Some(2), ..CrawlConfig::default() };
+
+        let report = check_links(server.url("/"), config).unwrap();
+
+        assert_eq!(report.checked_urls.len(), 2);
+        assert_eq!(report.skipped_urls.len(), 2);
+        assert!(report.skipped_urls.iter().all(|(_, reason)| *reason == SkipReason::MaxPages));
+    }
+
+    #[test]
+    fn test_missing_fragment_is_reported() {
+        let server = TestServer::new()
+            .route(
+                "/",
+                200,
+                r#"<a href="/docs#install">ok</a> <a href="/docs#nowhere">stale</a> <a href="/docs">plain</a>"#,
+            )
+            .route("/docs", 200, r#"<h2 id="install">Install</h2>"#)
+            .start();
+        let config = CrawlConfig { max_depth: Some(1), ..CrawlConfig::default() };
+
+        let report = check_links(server.url("/"), config).unwrap();
+
+        // `/docs` is fetched once for all three links.
+        let fetched = report.checked_urls.iter().filter(|checked| checked.url == server.url("/docs"));
+        assert_eq!(fetched.count(), 1);
+        let failures: Vec<_> = report.failures().collect();
+        assert_eq!(failures.len(), 1);
+        let (checked, error) = failures[0];
+        assert_eq!(checked.url, server.url("/docs#nowhere"));
+        assert_eq!(error.kind(), FailureKind::MissingFragment);
+        assert_eq!(checked.sources[0].text, "stale");
+    }
+
+    #[test]
+    fn test_transient_failures_are_retried() {
+        let server = TestServer::new()
+            .route("/", 200, r#"<a href="/busy">busy</a> <a href="/down">down</a> <a href="/gone">gone</a>"#)
+            .route("/busy", 200, "")
+            .fail_first("/busy", 2, 503, &[("Retry-After", "0")])
+            .fail_first("/down", 9, 502, &[])
+            .start();
+        let config = CrawlConfig {
+            retry: RetryPolicy { initial_backoff: Duration::from_millis(1), ..RetryPolicy::default() },
+            ..CrawlConfig::default()
+        };
+
+        let report = check_links(server.url("/"), config).unwrap();
+
+        // `/busy` recovers on the last retry; `/down` never does, and a 404
+        // is not worth retrying at all.
+        let mut bad_urls = report.bad_urls();
+        bad_urls.sort();
+        assert_eq!(bad_urls, vec![server.url("/down"), server.url("/gone")]);
+    }
+
+    /// Crawl an in-memory site from `https://example.com/`.
+    fn check_memory_site(fetcher: MemoryFetcher) -> (CrawlReport, Arc<MemoryFetcher>) {
+        check_memory_site_with(fetcher, CrawlConfig::default())
+    }
+
+    fn check_memory_site_with(fetcher: MemoryFetcher, config: CrawlConfig) -> (CrawlReport, Arc<MemoryFetcher>) {
+        let fetcher = Arc::new(fetcher);
+        let start_url = Url::parse("https://example.com/").unwrap();
+        let config = CrawlConfig { retry: RetryPolicy::none(), rate_limit: None, ..config };
+        let report = check_links_with(start_url, config, fetcher.clone());
+        (report, fetcher)
+    }
+
+    #[test]
+    fn test_cycles_are_fetched_once() {
+        let (report, fetcher) = check_memory_site(
+            MemoryFetcher::new()
+                .page("https://example.com/", r#"<a href="/a">a</a>"#)
+                .page("https://example.com/a", r#"<a href="/b">b</a> <a href="/">home</a>"#)
+                .page("https://example.com/b", r#"<a href="/a">a</a> <a href="/">home</a>"#),
+        );
+
+        assert_eq!(report.checked_urls.len(), 3);
+        assert!(report.bad_urls().is_empty());
+        let page_fetches = fetcher.requests().into_iter().filter(|url| url.path() != "/robots.txt").count();
+        assert_eq!(page_fetches, 3);
+    }
+
+    #[test]
+    fn test_redirects_are_recorded_and_loops_fail() {
+        let (report, _) = check_memory_site(
+            MemoryFetcher::new()
+                .page("https://example.com/", r#"<a href="/old">old</a> <a href="/loop">loop</a>"#)
+                .redirect("https://example.com/old", 301, "/new")
+                .page("https://example.com/new", r#"<a href="/gone">gone</a>"#)
+                .redirect("https://example.com/loop", 302, "/loop"),
+        );
+
+        let old = report.checked_urls.iter().find(|checked| checked.url.path() == "/old").unwrap();
+        let new = Url::parse("https://example.com/new").unwrap();
+        assert_eq!(old.redirect_chain, vec![Redirect { status: StatusCode::MOVED_PERMANENTLY, url: new.clone() }]);
+        let permanent: Vec<_> = report.permanent_redirects().map(|(checked, target)| (&checked.url, target)).collect();
+        assert_eq!(permanent, vec![(&old.url, &new)]);
+        // Links on the redirect target resolve against where it ended up.
+        let mut bad_paths: Vec<_> = report.bad_urls().iter().map(|url| url.path().to_string()).collect();
+        bad_paths.sort();
+        assert_eq!(bad_paths, vec!["/gone", "/loop"]);
+        let (_, error) = report.failures().find(|(checked, _)| checked.url.path() == "/loop").unwrap();
+        assert_eq!(error.kind(), FailureKind::Redirect);
+        assert!(matches!(error, Error::RedirectLoop(_)));
+    }
+
+    #[test]
+    fn test_spellings_of_a_page_are_checked_once() {
+        let site = || {
+            MemoryFetcher::new()
+                .page(
+                    "https://example.com/",
+                    r#"<a href="/docs">docs</a> <a href="/docs/">slash</a> <a href="HTTPS://EXAMPLE.COM:443/docs#intro">loud</a>
+                    <a href="/docs?utm_source=feed">tracked</a> <a href="/search?b=2&a=1">b a</a> <a href="/search?a=1&b=2">a b</a>"#,
+                )
+                .page("https://example.com/docs", r#"<h1 id="intro">Docs</h1>"#)
+                .page("https://example.com/search?b=2&a=1", "")
+        };
+
+        let (report, fetcher) = check_memory_site(site());
+
+        // The start page, `/docs` and `/search`, each fetched as first linked.
+        assert_eq!(report.checked_urls.len(), 3);
+        assert!(report.bad_urls().is_empty());
+        let docs = report.checked_urls.iter().find(|checked| checked.url.path() == "/docs").unwrap();
+        assert_eq!(docs.sources.len(), 4);
+        assert_eq!(fetcher.requests().iter().filter(|url| url.path() != "/robots.txt").count(), 3);
+
+        let config = CrawlConfig { normalize: NormalizeRules::minimal(), ..CrawlConfig::default() };
+        let (report, _) = check_memory_site_with(site(), config);
+        assert_eq!(report.checked_urls.len(), 6);
+    }
+
+    #[test]
+    fn test_server_errors_keep_their_status() {
+        let (report, _) = check_memory_site(
+            MemoryFetcher::new()
+                .page("https://example.com/", r#"<img src="/broken.png">"#)
+                .response("https://example.com/broken.png", 500, &[], ""),
+        );
+
+        let (checked, error) = report.failures().next().unwrap();
+        assert_eq!(checked.status, Some(StatusCode::INTERNAL_SERVER_ERROR));
+        assert_eq!(error.kind(), FailureKind::HttpStatus);
+        assert_eq!(checked.sources[0].kind, ResourceKind::Image);
+    }
+
+    /// Passes requests through and cancels the crawl once `stop_at`
+    /// has been fetched.
+    struct StopAt {
+        inner: MemoryFetcher,
+        stop_at: Url,
+        cancel: CancelToken,
+    }
+
+    impl Fetcher for StopAt {
+        fn get(&self, url: &Url, headers: &header::HeaderMap) -> Result<Response, Error> {
+            if *url == self.stop_at {
+                self.cancel.cancel();
+            }
+            self.inner.get(url, headers)
+        }
+    }
+
+    #[test]
+    fn test_stopped_crawl_resumes_from_checkpoint() {
+        let site = || {
+            MemoryFetcher::new()
+                .page("https://example.com/", r#"<a href="/a">a</a> <a href="/missing">missing</a>"#)
+                .page("https://example.com/a", r#"<a href="/b">b</a>"#)
+                .page("https://example.com/b", r#"<a href="/c">c</a>"#)
+                .page("https://example.com/c", "")
+        };
+        let path = std::env::temp_dir().join(format!("link-checker-test-{}.json", std::process::id()));
+        let checkpoint = CheckpointConfig::new(&path);
+        let config = |cancel| CrawlConfig {
+            retry: RetryPolicy::none(),
+            rate_limit: None,
+            checkpoint: Some(checkpoint.clone()),
+            cancel,
+            ..CrawlConfig::default()
+        };
+        let start_url = Url::parse("https://example.com/").unwrap();
+        let cancel = CancelToken::default();
+        let stop_at = StopAt { inner: site(), stop_at: start_url.join("/a").unwrap(), cancel: cancel.clone() };
+
+        let first = check_links_with(start_url.clone(), config(cancel), Arc::new(stop_at));
+        assert!(first.interrupted);
+        assert!(first.checked_urls.len() < 5);
+
+        let fetcher = Arc::new(site());
+        let resumed =
+            resume_links_with(Checkpoint::load(&path).unwrap(), config(CancelToken::default()), fetcher.clone());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(!resumed.interrupted);
+        let mut checked: Vec<_> = resumed.checked_urls.iter().map(|checked| checked.url.path().to_string()).collect();
+        checked.sort();
+        assert_eq!(checked, vec!["/", "/a", "/b", "/c", "/missing"]);
+        assert_eq!(resumed.bad_urls(), vec![start_url.join("/missing").unwrap()]);
+        // The start page was checked before the stop and is not fetched again.
+        assert!(!fetcher.requests().contains(&start_url));
+    }
+
+    #[test]
+    fn test_cancelled_crawl_drains_requests_in_progress() {
+        let site = MemoryFetcher::new()
+            .page("https://example.com/", r#"<a href="/a">a</a>"#)
+            .page("https://example.com/a", r#"<a href="/b">b</a> <a href="/c">c</a>"#);
+        let start_url = Url::parse("https://example.com/").unwrap();
+        let cancel = CancelToken::default();
+        let stop_at = StopAt { inner: site, stop_at: start_url.join("/a").unwrap(), cancel: cancel.clone() };
+        let (progress, events) = Progress::channel();
+        let config = CrawlConfig { retry: RetryPolicy::none(), rate_limit: None, progress, cancel, ..CrawlConfig::default() };
+
+        let report = check_links_with(start_url, config, Arc::new(stop_at));
+        let events: Vec<ProgressEvent> = events.iter().collect();
+
+        assert!(report.interrupted);
+        // `/a` was being fetched when the crawl was cancelled and is still
+        // recorded, while the links it led to are never started.
+        let mut checked: Vec<_> = report.checked_urls.iter().map(|checked| checked.url.path()).collect();
+        checked.sort();
+        assert_eq!(checked, vec!["/", "/a"]);
+        let mut stats = ProgressStats::default();
+        for event in &events {
+            stats.record(event);
+        }
+        assert_eq!(stats.started, 2);
+        assert_eq!(stats.finished, 2);
+        assert_eq!(stats.in_progress(), 0);
+        assert!(matches!(&events[0], ProgressEvent::Queued { url, depth: 0 } if url.path() == "/"));
+    }
+
+    #[test]
+    fn test_sitemap_pages_are_checked_and_orphans_reported() {
+        let sitemap = Url::parse("https://example.com/sitemap.xml").unwrap();
+        let fetcher = MemoryFetcher::new()
+            .page("https://example.com/", r#"<a href="/linked">linked</a>"#)
+            .page("https://example.com/linked", "")
+            .page("https://example.com/orphan", "")
+            .response(
+                sitemap.as_str(),
+                200,
+                &[("Content-Type", "application/xml")],
+                r#"<urlset>
+                    <url><loc>https://example.com/</loc></url>
+                    <url><loc>https://example.com/linked</loc></url>
+                    <url><loc>https://example.com/orphan</loc></url>
+                    <url><loc>https://example.com/gone</loc></url>
+                </urlset>"#,
+            );
+        let config = CrawlConfig { sitemaps: vec![sitemap.clone()], ..CrawlConfig::default() };
+
+        let (report, _) = check_memory_site_with(fetcher, config);
+
+        assert_eq!(report.orphan_urls, vec![Url::parse("https://example.com/orphan").unwrap()]);
+        let by_page = report.failures_by_page();
+        let broken: Vec<_> = by_page[&Some(&sitemap)].iter().map(|broken| broken.url.path()).collect();
+        assert_eq!(broken, vec!["/gone"]);
+        assert_eq!(report.checked_urls.len(), 4);
+    }
+
+    #[test]
+    fn test_scope_decides_whose_links_are_followed() {
+        let site = || {
+            MemoryFetcher::new()
+                .page(
+                    "https://example.com/",
+                    r#"<a href="https://docs.example.com/guide">docs</a>
+                    <a href="mailto:team@example.com">mail</a> <a href="javascript:void(0)">menu</a>"#,
+                )
+                .page("https://docs.example.com/guide", r#"<a href="/setup">setup</a>"#)
+                .page("https://docs.example.com/setup", "")
+        };
+        let checked = |report: &CrawlReport| {
+            let mut checked: Vec<_> = report.checked_urls.iter().map(|checked| checked.url.to_string()).collect();
+            checked.sort();
+            checked
+        };
+
+        let (exact, _) = check_memory_site(site());
+        let config = CrawlConfig { scope: ScopePolicy::Subdomains, ..CrawlConfig::default() };
+        let (subdomains, _) = check_memory_site_with(site(), config);
+
+        assert_eq!(checked(&exact), vec!["https://docs.example.com/guide", "https://example.com/"]);
+        assert_eq!(
+            checked(&subdomains),
+            vec!["https://docs.example.com/guide", "https://docs.example.com/setup", "https://example.com/"]
+        );
+        let non_http: Vec<_> = subdomains.non_http_urls.iter().map(Url::scheme).collect();
+        assert_eq!(non_http, vec!["javascript", "mailto"]);
+        assert!(subdomains.bad_urls().is_empty());
+    }
+
+    #[test]
+    fn test_leaf_links_are_checked_with_head() {
+        let site = || {
+            MemoryFetcher::new()
+                .page(
+                    "https://example.com/",
+                    r#"<img src="https://example.org/logo.png"> <a href="https://example.org/gone">gone</a>"#,
+                )
+                .bytes("https://example.org/logo.png", 200, &[("Content-Type", "image/png")], b"\x89PNG")
+        };
+
+        let (report, fetcher) = check_memory_site(site());
+        let (refused, refusing_fetcher) = check_memory_site(site().refuse_head());
+
+        let gone = Url::parse("https://example.org/gone").unwrap();
+        assert_eq!(report.bad_urls(), vec![gone.clone()]);
+        assert_eq!(refused.bad_urls(), vec![gone]);
+        // The image only needed a HEAD. The missing page got a GET as well,
+        // in case the server only mishandles HEAD.
+        let gets = |fetcher: &MemoryFetcher, path: &str| {
+            let all = fetcher.requests().iter().filter(|url| url.path() == path).count();
+            let heads = fetcher.head_requests().iter().filter(|url| url.path() == path).count();
+            all - heads
+        };
+        assert_eq!(gets(&fetcher, "/logo.png"), 0);
+        assert_eq!(gets(&fetcher, "/gone"), 1);
+        assert_eq!(gets(&refusing_fetcher, "/logo.png"), 1);
+        // Pages whose links are followed are fetched with GET straight away.
+        assert!(!fetcher.head_requests().iter().any(|url| url.path() == "/"));
+    }
+
+    #[test]
+    fn test_only_html_pages_are_parsed() {
+        let (report, _) = check_memory_site(
+            MemoryFetcher::new()
+                .page("https://example.com/", r#"<a href="/report.pdf">pdf</a> <a href="/page.xhtml">xhtml</a>"#)
+                .response(
+                    "https://example.com/report.pdf",
+                    200,
+                    &[("Content-Type", "application/pdf")],
+                    r#"<a href="/from-pdf">not a link</a>"#,
+                )
+                .response(
+                    "https://example.com/page.xhtml",
+                    200,
+                    &[("Content-Type", "application/xhtml+xml; charset=utf-8")],
+                    r#"<a href="/from-xhtml">a link</a>"#,
+                ),
+        );
+
+        let checked: Vec<_> = report.checked_urls.iter().map(|checked| checked.url.path()).collect();
+        assert!(!checked.contains(&"/from-pdf"));
+        assert!(checked.contains(&"/from-xhtml"));
+        assert!(report.bad_urls().iter().all(|url| url.path() == "/from-xhtml"));
+    }
+
+    #[test]
+    fn test_oversized_page_is_not_read() {
+        let config = CrawlConfig {
+            limits: FetchLimits { max_body_size: 64, ..FetchLimits::default() },
+            ..CrawlConfig::default()
+        };
+        let (report, _) = check_memory_site_with(
+            MemoryFetcher::new()
+                .page("https://example.com/", r#"<a href="/big">big</a>"#)
+                .page("https://example.com/big", &"<p>padding</p>".repeat(100)),
+            config,
+        );
+
+        let big = report.checked_urls.iter().find(|checked| checked.url.path() == "/big").unwrap();
+        assert!(matches!(big.error, Some(Error::BodyTooLarge(64))));
+        assert_eq!(big.error.as_ref().unwrap().kind(), FailureKind::BodyTooLarge);
+    }
+
+    #[test]
+    fn test_rules_accept_statuses_per_pattern() {
+        let mut members = UrlRule::new(UrlPattern::parse("/members/*").unwrap());
+        members.accept.push(StatusCode::FORBIDDEN);
+        let config = CrawlConfig { rules: UrlRules(vec![members]), ..CrawlConfig::default() };
+        let (report, _) = check_memory_site_with(
+            MemoryFetcher::new()
+                .page("https://example.com/", r#"<a href="/members/area">members</a> <a href="/admin">admin</a>"#)
+                .response("https://example.com/members/area", 403, &[("Content-Type", "text/html")], "")
+                .response("https://example.com/admin", 403, &[("Content-Type", "text/html")], ""),
+            config,
+        );
+
+        assert_eq!(report.bad_urls(), vec![Url::parse("https://example.com/admin").unwrap()]);
+        let area = report.checked_urls.iter().find(|checked| checked.url.path() == "/members/area").unwrap();
+        assert_eq!(area.status, Some(StatusCode::FORBIDDEN));
+    }
+
+    fn cache_config(name: &str) -> CacheConfig {
+        let path = std::env::temp_dir().join(format!("link-checker-{name}-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        CacheConfig::new(path)
+    }
+
+    #[test]
+    fn test_unchanged_pages_reuse_cached_links() {
+        let cache = cache_config("cache-unchanged");
+        let config = || CrawlConfig { cache: Some(cache.clone()), ..CrawlConfig::default() };
+        let home = |body| {
+            MemoryFetcher::new().response(
+                "https://example.com/",
+                200,
+                &[("Content-Type", "text/html"), ("ETag", "\"v1\"")],
+                body,
+            )
+        };
+
+        let (first, _) = check_memory_site_with(home(r#"<a href="/missing">gone</a>"#), config());
+        // Same ETag, so the server answers 304 and the body is never seen.
+        let (second, fetcher) = check_memory_site_with(home("changed without a new ETag"), config());
+        std::fs::remove_file(&cache.path).unwrap();
+
+        let missing = Url::parse("https://example.com/missing").unwrap();
+        assert_eq!(first.bad_urls(), vec![missing.clone()]);
+        assert_eq!(second.bad_urls(), vec![missing]);
+        assert_eq!(second.checked_urls[0].status, Some(StatusCode::OK));
+        assert!(fetcher.requests().iter().any(|url| url.path() == "/"));
+    }
+
+    #[test]
+    fn test_recent_external_links_are_not_rechecked() {
+        let cache = CacheConfig {
+            recheck_external_after: Some(Duration::from_secs(60 * 60)),
+            ..cache_config("cache-external")
+        };
+        let config = || CrawlConfig { cache: Some(cache.clone()), ..CrawlConfig::default() };
+        let site = || {
+            MemoryFetcher::new()
+                .page("https://example.com/", r#"<a href="https://example.org/ok">ok</a>"#)
+                .page("https://example.org/ok", "")
+        };
+
+        let (first, first_fetcher) = check_memory_site_with(site(), config());
+        let (second, second_fetcher) = check_memory_site_with(site(), config());
+        std::fs::remove_file(&cache.path).unwrap();
+
+        let is_external = |url: &Url| url.host_str() == Some("example.org");
+        assert!(first_fetcher.requests().iter().any(is_external));
+        assert!(!second_fetcher.requests().iter().any(is_external));
+        assert!(first.bad_urls().is_empty());
+        assert!(second.bad_urls().is_empty());
+        assert_eq!(second.checked_urls.len(), 2);
+        // Pages on the site itself are still revalidated every time.
+        assert!(second_fetcher.requests().iter().any(|url| url.path() == "/"));
+    }
+
+    #[test]
+    fn test_unreachable_host_is_a_connect_error() {
+        // Nothing listens on port 1.
+        let report = check_links(Url::parse("http://localhost:1/").unwrap(), CrawlConfig::default()).unwrap();
+
+        let by_page = report.failures_by_page();
+        let start = &by_page[&None];
+        assert_eq!(start[0].error.kind(), FailureKind::Connect);
+    }
+}
@@ -0,0 +1,414 @@
+// Async link checker
+// The same crawl as `check_links`, driven by tokio tasks instead of OS threads.
+// Fetches run in a `JoinSet` and a new one is only spawned while fewer than
+// `max_in_flight` are running, so the frontier can hold thousands of pages
+// without a thread (or an open socket) per page.
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use reqwest::{redirect, Client, Method, Response, Url};
+use tokio::task::JoinSet;
+
+use super::config::CrawlConfig;
+use super::fetch;
+use super::rate_limit::HostRateLimiter;
+use super::redirect::{Redirect, RedirectChain, RedirectPolicy};
+use super::robots::{Robots, RobotsPolicy, Verdict, MAX_ROBOTS_BYTES};
+use super::{
+  head_is_enough, parse_body, wants_body, CrawlCommand, CrawlResult, CrawlState, Error, Visit,
+  CONFIG_FILE, USER_AGENT,
+};
+
+/// Send `method` requests along the redirects from `start_url` to its final
+/// response.
+async fn follow(
+  client: &Client,
+  method: Method,
+  start_url: &Url,
+  policy: &RedirectPolicy,
+  config: &CrawlConfig,
+) -> Result<(Response, Vec<Redirect>), Error> {
+  let send = |url: &Url| {
+    let timeout = config
+      .rules
+      .timeout(url)
+      .unwrap_or(config.limits.read_timeout);
+    client
+      .request(method.clone(), url.clone())
+      .headers(config.rules.headers(url))
+      .timeout(timeout)
+      .send()
+  };
+  let mut chain = RedirectChain::new(policy, start_url);
+  let mut response = send(start_url).await?;
+  while let Some(next_url) = chain.next(response.url(), response.status(), response.headers())? {
+    response = send(&next_url).await?;
+  }
+  Ok((response, chain.hops))
+}
+
+/// Like the threaded crawler's `fetch_page`: leaf links are asked with HEAD
+/// first, and with GET unless `head_is_enough` says the answer will do.
+async fn fetch_page(
+  client: &Client,
+  command: &CrawlCommand,
+  config: &CrawlConfig,
+) -> Result<(Response, Vec<Redirect>), Error> {
+  if !command.extract_links && !command.collect_anchors {
+    let (response, redirect_chain) = follow(
+      client,
+      Method::HEAD,
+      &command.url,
+      &config.redirects,
+      config,
+    )
+    .await?;
+    if head_is_enough(response.status()) {
+      return Ok((response, redirect_chain));
+    }
+  }
+  follow(client, Method::GET, &command.url, &config.redirects, config).await
+}
+
+/// Like `robots::fetch_robots`, over the async client.
+async fn fetch_robots(client: &Client, url: &Url, config: &CrawlConfig) -> Robots {
+  let Ok(robots_url) = url.join("/robots.txt") else {
+    return Robots::allow_all();
+  };
+  let policy = RedirectPolicy::default();
+  match follow(client, Method::GET, &robots_url, &policy, config).await {
+    Ok((response, _)) => match Robots::for_status(response.status()) {
+      Some(robots) => robots,
+      None => Robots::from_body(
+        read_body(response, MAX_ROBOTS_BYTES)
+          .await
+          .map(|body| String::from_utf8_lossy(&body).into_owned()),
+      ),
+    },
+    // Let the page fetch report an unreachable host itself.
+    Err(_) => Robots::allow_all(),
+  }
+}
+
+/// The async twin of `RobotsPolicy::check`, which also waits out the
+/// host's Crawl-delay.
+async fn check_robots(
+  client: &Client,
+  robots_policy: &RobotsPolicy,
+  url: &Url,
+  config: &CrawlConfig,
+) -> Verdict {
+  let robots = match robots_policy.cached(url) {
+    Some(robots) => robots,
+    None => robots_policy.remember(url, fetch_robots(client, url, config).await),
+  };
+  if !robots.is_allowed(url) {
+    return Verdict::Disallowed;
+  }
+  if let Some(delay) = robots.crawl_delay() {
+    sleep_unless_zero(robots_policy.reserve_turn(url, delay)).await;
+  }
+  Verdict::Allowed
+}
+
+/// The body, read a chunk at a time and never past `limit` bytes, like
+/// `fetch::Response::bytes`.
+async fn read_body(mut response: Response, limit: u64) -> Result<Vec<u8>, Error> {
+  fetch::check_content_length(response.headers(), limit)?;
+  let mut body = Vec::new();
+  while let Some(chunk) = response.chunk().await? {
+    body.extend_from_slice(&chunk);
+    if body.len() as u64 > limit {
+      return Err(Error::BodyTooLarge(limit));
+    }
+  }
+  Ok(body)
+}
+
+async fn visit_page(
+  client: &Client,
+  command: &CrawlCommand,
+  config: &CrawlConfig,
+) -> Result<Visit, Error> {
+  eprintln!("Checking {:#}", command.url);
+  let (response, redirect_chain) = fetch_page(client, command, config).await?;
+  let status = response.status();
+  if !config.rules.accepts(&command.url, status) {
+    return Err(Error::bad_response(status, response.headers()));
+  }
+
+  let base_url = response.url().to_owned();
+  let mut visit = Visit {
+    status,
+    redirect_chain,
+    links: Vec::new(),
+    anchors: None,
+  };
+  if !status.is_success() || !wants_body(command, response.headers()) {
+    return Ok(visit);
+  }
+
+  let body = read_body(response, config.limits.max_body_size).await?;
+  parse_body(
+    &mut visit,
+    &base_url,
+    &String::from_utf8_lossy(&body),
+    command,
+    config,
+  );
+  Ok(visit)
+}
+
+/// The async twin of the threaded crawler's `visit_with_retries`.
+async fn visit_with_retries(
+  client: &Client,
+  command: &CrawlCommand,
+  config: &CrawlConfig,
+  rate_limiter: &HostRateLimiter,
+) -> Result<Visit, Error> {
+  let mut attempt = 0;
+  loop {
+    sleep_unless_zero(rate_limiter.reserve(&command.url)).await;
+    let error = match visit_page(client, command, config).await {
+      Ok(visit) => return Ok(visit),
+      Err(error) if error.is_retryable() => error,
+      Err(error) => return Err(error),
+    };
+    attempt += 1;
+    let Some(delay) = config.retry.delay(attempt, error.retry_after()) else {
+      return Err(error);
+    };
+    eprintln!("Retrying {:#} in {delay:?} after: {error:#}", command.url);
+    sleep_unless_zero(delay).await;
+  }
+}
+
+async fn sleep_unless_zero(delay: Duration) {
+  if !delay.is_zero() {
+    tokio::time::sleep(delay).await;
+  }
+}
+
+/// Crawl from `start_url` and return the URLs that could not be fetched.
+/// At most `max_in_flight` requests are outstanding at any time. Fails only
+/// if the HTTP client cannot be set up.
+pub async fn check_links(
+  start_url: Url,
+  config: CrawlConfig,
+  max_in_flight: usize,
+) -> Result<Vec<Url>, reqwest::Error> {
+  assert!(max_in_flight > 0, "max_in_flight must be at least 1");
+  // `Client` is an `Arc` internally, so every task gets a cheap clone that
+  // shares the same connection pool.
+  let client = Client::builder()
+    .user_agent(USER_AGENT)
+    .redirect(redirect::Policy::none())
+    .connect_timeout(config.limits.connect_timeout)
+    .build()?;
+  let rate_limiter = Arc::new(HostRateLimiter::new(config.rate_limit));
+  let robots_policy = Arc::new(RobotsPolicy::new());
+  let config = Arc::new(config);
+  let mut crawl_state = CrawlState::new(&[start_url], config.clone());
+  let mut queued_commands = VecDeque::from(crawl_state.start_commands());
+  let mut in_flight = JoinSet::new();
+
+  let mut bad_urls = Vec::new();
+  loop {
+    while in_flight.len() < max_in_flight {
+      let Some(crawl_command) = queued_commands.pop_front() else {
+        break;
+      };
+      let client = client.clone();
+      let config = config.clone();
+      let rate_limiter = rate_limiter.clone();
+      let robots_policy = robots_policy.clone();
+      in_flight.spawn(async move {
+        let started = Instant::now();
+        let visit = match check_robots(&client, &robots_policy, &crawl_command.url, &config).await {
+          Verdict::Disallowed => Err(Error::DisallowedByRobots),
+          Verdict::Allowed => {
+            visit_with_retries(&client, &crawl_command, &config, &rate_limiter).await
+          }
+        };
+        CrawlResult {
+          command: crawl_command,
+          elapsed: started.elapsed(),
+          visit: Some(visit),
+        }
+      });
+    }
+
+    let Some(joined) = in_flight.join_next().await else {
+      // Nothing running and nothing queued: the crawl is complete.
+      break;
+    };
+    let CrawlResult { command, visit, .. } = joined.expect("crawl task panicked");
+    let Some(visit) = visit else {
+      continue;
+    };
+    match visit {
+      Ok(visit) => {
+        if let Some(anchors) = visit.anchors {
+          crawl_state.record_anchors(&command.url, anchors);
+        }
+        queued_commands.extend(crawl_state.new_commands(command.depth, visit.links));
+      }
+      Err(Error::DisallowedByRobots) => {
+        eprintln!("Skipping {:#}: disallowed by robots.txt", command.url);
+      }
+      Err(error) => {
+        bad_urls.push(command.url);
+        eprintln!("Got crawling error: {:#}", error);
+      }
+    }
+  }
+  for (url, _) in crawl_state.take_missing_fragments() {
+    eprintln!("Missing fragment: {url:#}");
+    bad_urls.push(url);
+  }
+  Ok(bad_urls)
+}
+
+/// Crawl a sample site with the settings in `CONFIG_FILE`, like
+/// `link_checker`.
+#[tokio::main]
+pub async fn async_link_checker() {
+  let start_url = Url::parse("https://www.google.org").unwrap();
+  let file_config = match CrawlConfig::from_file_or_default(Path::new(CONFIG_FILE)) {
+    Ok(config) => config,
+    Err(error) => {
+      eprintln!("{error}");
+      return;
+    }
+  };
+  let config = CrawlConfig {
+    max_pages: file_config.max_pages.or(Some(200)),
+    ..file_config
+  };
+  match check_links(start_url, config, 64).await {
+    Ok(bad_urls) => println!("Bad URLs: {:#?}", bad_urls),
+    Err(error) => println!("Could not start the crawl: {error:#}"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::fetch::FetchLimits;
+  use super::super::test_server::TestServer;
+  use super::*;
+
+  #[tokio::test]
+  async fn test_reports_broken_links_only() {
+    let server = TestServer::new()
+      .route(
+        "/",
+        200,
+        r#"<a href="/ok">ok</a> <a href="/missing">missing</a>"#,
+      )
+      .route("/ok", 200, r#"<a href="/">home</a>"#)
+      .start();
+
+    let bad_urls = check_links(server.url("/"), CrawlConfig::default(), 2)
+      .await
+      .unwrap();
+
+    assert_eq!(bad_urls, vec![server.url("/missing")]);
+  }
+
+  #[tokio::test]
+  async fn test_config_file_applies() {
+    let server = TestServer::new()
+      .route(
+        "/",
+        200,
+        r#"<a href="/ok">ok</a> <a href="/legacy/page">legacy</a>"#,
+      )
+      .route("/ok", 200, "")
+      .start();
+    let path = std::env::temp_dir().join(format!(
+      "link-checker-async-config-{}.toml",
+      std::process::id()
+    ));
+    std::fs::write(&path, "exclude = [\"/legacy/*\"]\nthreads = 2\n").unwrap();
+
+    let config = CrawlConfig::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let bad_urls = check_links(server.url("/"), config, 2).await.unwrap();
+
+    assert!(bad_urls.is_empty(), "{bad_urls:?}");
+  }
+
+  #[tokio::test]
+  async fn test_oversized_pages_fail() {
+    let server = TestServer::new()
+      .route("/", 200, r#"<a href="/big">big</a>"#)
+      .route("/big", 200, &"<p>filler</p>".repeat(100))
+      .start();
+    let config = CrawlConfig {
+      limits: FetchLimits {
+        max_body_size: 1000,
+        ..FetchLimits::default()
+      },
+      ..CrawlConfig::default()
+    };
+
+    let bad_urls = check_links(server.url("/"), config, 2).await.unwrap();
+
+    assert_eq!(bad_urls, vec![server.url("/big")]);
+  }
+
+  #[tokio::test]
+  async fn test_only_html_is_parsed() {
+    let server = TestServer::new()
+      .route("/", 200, r#"<a href="/notes.txt">notes</a>"#)
+      .route_with_headers(
+        "/notes.txt",
+        200,
+        &[("Content-Type", "text/plain")],
+        r#"<a href="/missing">not a link</a>"#,
+      )
+      .start();
+
+    let bad_urls = check_links(server.url("/"), CrawlConfig::default(), 2)
+      .await
+      .unwrap();
+
+    assert!(bad_urls.is_empty(), "{bad_urls:?}");
+  }
+
+  #[tokio::test]
+  async fn test_robots_txt_is_obeyed() {
+    let server = TestServer::new()
+      .route("/robots.txt", 200, "User-agent: *\nDisallow: /private\n")
+      .route(
+        "/",
+        200,
+        r#"<a href="/private">private</a> <a href="/missing">missing</a>"#,
+      )
+      .route("/private", 500, "")
+      .start();
+
+    let bad_urls = check_links(server.url("/"), CrawlConfig::default(), 2)
+      .await
+      .unwrap();
+
+    assert_eq!(bad_urls, vec![server.url("/missing")]);
+  }
+
+  #[tokio::test]
+  async fn test_single_request_in_flight() {
+    let server = TestServer::new()
+      .route("/", 200, r#"<a href="/a">a</a> <a href="/b">b</a>"#)
+      .route("/a", 200, r#"<a href="/b">b</a>"#)
+      .route("/b", 500, "")
+      .start();
+
+    let bad_urls = check_links(server.url("/"), CrawlConfig::default(), 1)
+      .await
+      .unwrap();
+
+    assert_eq!(bad_urls, vec![server.url("/b")]);
+  }
+}
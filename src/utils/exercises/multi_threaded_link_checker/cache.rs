@@ -0,0 +1,185 @@
+// Response cache
+// Repeated checks of the same site mostly find the same pages. The cache
+// keeps each page's validators (`ETag`, `Last-Modified`) and what was
+// learnt from it, so the next run can ask "has this changed?" and, on a
+// 304, reuse the cached links instead of downloading and parsing the page
+// again. External links can also be trusted for a while without any
+// request at all.
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+
+use super::redirect::Redirect;
+use super::{CrawlCommand, Link, Visit};
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+  pub path: PathBuf,
+  /// Reuse the result for an external URL checked less than this long ago
+  /// without sending any request. `None` always revalidates.
+  pub recheck_external_after: Option<Duration>,
+}
+
+impl CacheConfig {
+  pub fn new(path: impl Into<PathBuf>) -> CacheConfig {
+    CacheConfig {
+      path: path.into(),
+      recheck_external_after: None,
+    }
+  }
+}
+
+/// What a successful visit of one URL left behind.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+  etag: Option<String>,
+  last_modified: Option<String>,
+  checked_at: SystemTime,
+  status: u16,
+  redirect_chain: Vec<(u16, Url)>,
+  /// `None` if the page's links were not extracted on that visit.
+  links: Option<Vec<Link>>,
+  anchors: Option<HashSet<String>>,
+}
+
+impl CacheEntry {
+  /// Whether the entry has everything `command` would have fetched.
+  fn covers(&self, command: &CrawlCommand) -> bool {
+    (!command.extract_links || self.links.is_some())
+      && (!command.collect_anchors || self.anchors.is_some())
+  }
+
+  fn to_visit(&self) -> Visit {
+    Visit {
+      status: StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK),
+      redirect_chain: self
+        .redirect_chain
+        .iter()
+        .filter_map(|(status, url)| {
+          let status = StatusCode::from_u16(*status).ok()?;
+          Some(Redirect {
+            status,
+            url: url.clone(),
+          })
+        })
+        .collect(),
+      links: self.links.clone().unwrap_or_default(),
+      anchors: self.anchors.clone(),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct ResponseCache {
+  config: CacheConfig,
+  entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl ResponseCache {
+  /// Open the cache at `config.path`, starting empty if there is no file.
+  pub fn load(config: CacheConfig) -> io::Result<ResponseCache> {
+    let entries = match fs::read(&config.path) {
+      Ok(json) => serde_json::from_slice(&json)?,
+      Err(error) if error.kind() == io::ErrorKind::NotFound => HashMap::new(),
+      Err(error) => return Err(error),
+    };
+    Ok(ResponseCache {
+      config,
+      entries: Mutex::new(entries),
+    })
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.config.path
+  }
+
+  /// Write the cache back, replacing the file atomically.
+  pub fn save(&self) -> io::Result<()> {
+    let json = serde_json::to_vec(&*self.entries.lock().unwrap())?;
+    let partial = self.config.path.with_extension("partial");
+    fs::write(&partial, json)?;
+    fs::rename(&partial, &self.config.path)
+  }
+
+  /// The cached visit of an external URL checked recently enough to trust
+  /// without asking again.
+  pub fn fresh(&self, command: &CrawlCommand) -> Option<Visit> {
+    let trusted = self.config.recheck_external_after?;
+    if !command.external {
+      return None;
+    }
+    let entries = self.entries.lock().unwrap();
+    let entry = entries.get(command.url.as_str())?;
+    let age = entry.checked_at.elapsed().unwrap_or(Duration::MAX);
+    (age < trusted && entry.covers(command)).then(|| entry.to_visit())
+  }
+
+  /// `If-None-Match` and `If-Modified-Since` headers asking whether the
+  /// cached visit of `command` still holds. Empty if there is nothing to
+  /// ask about.
+  pub fn conditional_headers(&self, command: &CrawlCommand) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let entries = self.entries.lock().unwrap();
+    let Some(entry) = entries.get(command.url.as_str()) else {
+      return headers;
+    };
+    if !entry.covers(command) {
+      return headers;
+    }
+    let validators = [
+      (header::IF_NONE_MATCH, &entry.etag),
+      (header::IF_MODIFIED_SINCE, &entry.last_modified),
+    ];
+    for (name, value) in validators {
+      if let Some(value) = value.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+        headers.insert(name, value);
+      }
+    }
+    headers
+  }
+
+  /// The server answered 304: the cached visit still holds.
+  pub fn not_modified(&self, command: &CrawlCommand) -> Option<Visit> {
+    let mut entries = self.entries.lock().unwrap();
+    let entry = entries.get_mut(command.url.as_str())?;
+    entry.checked_at = SystemTime::now();
+    Some(entry.to_visit())
+  }
+
+  /// Remember a successful visit along with the response's validators.
+  pub fn store(&self, command: &CrawlCommand, headers: &HeaderMap, visit: &Visit) {
+    let header_text = |name| {
+      headers
+        .get(name)
+        .and_then(|value: &HeaderValue| value.to_str().ok())
+        .map(String::from)
+    };
+    let entry = CacheEntry {
+      etag: header_text(header::ETAG),
+      last_modified: header_text(header::LAST_MODIFIED),
+      checked_at: SystemTime::now(),
+      status: visit.status.as_u16(),
+      redirect_chain: visit
+        .redirect_chain
+        .iter()
+        .map(|redirect| (redirect.status.as_u16(), redirect.url.clone()))
+        .collect(),
+      links: command.extract_links.then(|| visit.links.clone()),
+      anchors: visit.anchors.clone(),
+    };
+    let mut entries = self.entries.lock().unwrap();
+    entries.insert(command.url.as_str().to_string(), entry);
+  }
+
+  /// Forget a URL that failed, so a later run does not trust it.
+  pub fn remove(&self, command: &CrawlCommand) {
+    self.entries.lock().unwrap().remove(command.url.as_str());
+  }
+}
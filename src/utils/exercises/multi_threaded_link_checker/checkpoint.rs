@@ -0,0 +1,150 @@
+// Checkpoints
+// A long crawl periodically writes its state to disk: the crawl bookkeeping,
+// the commands still waiting for a result and everything checked so far. A
+// crawl resumed from that file picks up where it stopped, and a cancelled
+// crawl writes one last checkpoint before it returns.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+
+use super::config::CrawlConfig;
+use super::redirect::Redirect;
+use super::report::{CheckedUrl, CrawlReport};
+use super::{CrawlCommand, CrawlState, Error, FailureKind};
+
+#[derive(Debug, Clone)]
+pub struct CheckpointConfig {
+  pub path: PathBuf,
+  /// Time between two checkpoints while the crawl runs.
+  pub interval: Duration,
+}
+
+impl CheckpointConfig {
+  pub fn new(path: impl Into<PathBuf>) -> CheckpointConfig {
+    CheckpointConfig {
+      path: path.into(),
+      interval: Duration::from_secs(30),
+    }
+  }
+}
+
+/// A checked URL as written to disk. Errors cannot be serialized, so only
+/// their kind and description are kept.
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedUrl {
+  url: Url,
+  status: Option<u16>,
+  redirect_chain: Vec<(u16, Url)>,
+  elapsed: Duration,
+  error: Option<(FailureKind, String)>,
+}
+
+impl SavedUrl {
+  fn new(checked: &CheckedUrl) -> SavedUrl {
+    SavedUrl {
+      url: checked.url.clone(),
+      status: checked.status.map(|status| status.as_u16()),
+      redirect_chain: checked
+        .redirect_chain
+        .iter()
+        .map(|redirect| (redirect.status.as_u16(), redirect.url.clone()))
+        .collect(),
+      elapsed: checked.elapsed,
+      error: checked
+        .error
+        .as_ref()
+        .map(|error| (error.kind(), error.to_string())),
+    }
+  }
+
+  fn restore(self) -> CheckedUrl {
+    let status = self
+      .status
+      .and_then(|status| StatusCode::from_u16(status).ok());
+    CheckedUrl {
+      url: self.url,
+      status,
+      redirect_chain: self
+        .redirect_chain
+        .into_iter()
+        .filter_map(|(status, url)| {
+          let status = StatusCode::from_u16(status).ok()?;
+          Some(Redirect { status, url })
+        })
+        .collect(),
+      elapsed: self.elapsed,
+      sources: Vec::new(),
+      error: self.error.map(|(kind, message)| Error::Restored {
+        kind,
+        status,
+        message,
+      }),
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct CheckpointRef<'a> {
+  state: &'a CrawlState,
+  frontier: Vec<&'a CrawlCommand>,
+  checked_urls: Vec<SavedUrl>,
+  disallowed_urls: &'a [Url],
+}
+
+#[derive(Deserialize)]
+pub struct Checkpoint {
+  state: CrawlState,
+  /// Commands that had no result yet.
+  frontier: Vec<CrawlCommand>,
+  checked_urls: Vec<SavedUrl>,
+  disallowed_urls: Vec<Url>,
+}
+
+impl Checkpoint {
+  /// Write the crawl so far to `path`. The file is replaced atomically, so
+  /// a crash while saving leaves the previous checkpoint intact.
+  pub fn save<'a>(
+    path: &Path,
+    state: &CrawlState,
+    frontier: impl Iterator<Item = &'a CrawlCommand>,
+    report: &CrawlReport,
+  ) -> io::Result<()> {
+    let checkpoint = CheckpointRef {
+      state,
+      frontier: frontier.collect(),
+      checked_urls: report.checked_urls.iter().map(SavedUrl::new).collect(),
+      disallowed_urls: &report.disallowed_urls,
+    };
+    let json = serde_json::to_vec(&checkpoint)?;
+    let partial = path.with_extension("partial");
+    fs::write(&partial, json)?;
+    fs::rename(&partial, path)
+  }
+
+  pub fn load(path: &Path) -> io::Result<Checkpoint> {
+    let json = fs::read(path)?;
+    Ok(serde_json::from_slice(&json)?)
+  }
+
+  /// Split the checkpoint back into what the crawl loop works with. The
+  /// config is not saved, so the caller passes it in again.
+  pub fn restore(self, config: Arc<CrawlConfig>) -> (CrawlState, Vec<CrawlCommand>, CrawlReport) {
+    let mut state = self.state;
+    state.config = config;
+    let report = CrawlReport {
+      checked_urls: self
+        .checked_urls
+        .into_iter()
+        .map(SavedUrl::restore)
+        .collect(),
+      disallowed_urls: self.disallowed_urls,
+      ..CrawlReport::default()
+    };
+    (state, self.frontier, report)
+  }
+}
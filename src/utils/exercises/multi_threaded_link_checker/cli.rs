@@ -0,0 +1,314 @@
+// Command line
+// The `link-checker` binary: crawl one or more sites, or a site built into a
+// directory with `--root`, and fail when a link is broken, so it can run as a
+// CI step as it is. The log goes to stderr
+// while the crawl runs, so stdout holds nothing but the report unless
+// `--output` writes it to a file. Options given on the command line override
+// those in `--config`.
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Parser, ValueEnum};
+use reqwest::Url;
+
+use super::config::{CrawlConfig, UrlPattern};
+use super::local_site;
+use super::progress::{self, CancelToken};
+use super::report::{CrawlReport, OutputFormat};
+use super::scope::ScopePolicy;
+
+/// Check every link on a site and exit non-zero if any is broken.
+#[derive(Debug, Parser)]
+#[command(
+  name = "link-checker",
+  after_help = "Exit status: 0 if every link works, 1 if broken links were found, \
+                2 if the arguments were invalid or the crawl did not finish."
+)]
+struct Args {
+  /// Where to start crawling. Links are followed on the hosts of all of them.
+  #[arg(required_unless_present = "root", value_name = "URL")]
+  start_urls: Vec<Url>,
+  /// Check the site built into this directory instead of one on the web.
+  #[arg(
+    long,
+    value_name = "DIR",
+    conflicts_with = "start_urls",
+    requires = "site_url"
+  )]
+  root: Option<PathBuf>,
+  /// Where the site in `--root` will be deployed, so that absolute links to
+  /// it are read from the directory too.
+  #[arg(long, value_name = "URL", requires = "root")]
+  site_url: Option<Url>,
+  /// Read settings and per-URL rules from this TOML or JSON file.
+  #[arg(short, long, value_name = "FILE")]
+  config: Option<PathBuf>,
+  /// Worker threads making requests [default: 16].
+  #[arg(short = 'j', long, value_parser = clap::value_parser!(u32).range(1..))]
+  threads: Option<u32>,
+  /// How many links away from a start URL pages are followed.
+  #[arg(short = 'd', long)]
+  max_depth: Option<usize>,
+  /// Stop after checking this many URLs.
+  #[arg(long)]
+  max_pages: Option<usize>,
+  /// Only check URLs matching this glob, e.g. `https://example.com/docs/*`.
+  /// May be repeated.
+  #[arg(short, long, value_name = "GLOB", value_parser = parse_glob)]
+  include: Vec<UrlPattern>,
+  /// Never check URLs matching this glob. May be repeated.
+  #[arg(short, long, value_name = "GLOB", value_parser = parse_glob)]
+  exclude: Vec<UrlPattern>,
+  /// Which URLs belong to the site and have their links followed: `exact`
+  /// (the default), `subdomains`, `hosts=a.example,b.example:8080` or
+  /// `path=/docs/`.
+  #[arg(long, value_name = "SCOPE")]
+  scope: Option<ScopePolicy>,
+  /// Do not check links to other sites.
+  #[arg(long)]
+  no_external: bool,
+  /// Seconds a single request may take.
+  #[arg(long, value_name = "SECONDS")]
+  timeout: Option<u64>,
+  /// How to write the report.
+  #[arg(short, long, value_enum, default_value_t = Format::Text)]
+  format: Format,
+  /// Write the report to this file instead of stdout.
+  #[arg(short, long, value_name = "FILE")]
+  output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+  Text,
+  Json,
+  Junit,
+  Sarif,
+}
+
+impl From<Format> for OutputFormat {
+  fn from(format: Format) -> OutputFormat {
+    match format {
+      Format::Text => OutputFormat::Text,
+      Format::Json => OutputFormat::Json,
+      Format::Junit => OutputFormat::JUnit,
+      Format::Sarif => OutputFormat::Sarif,
+    }
+  }
+}
+
+fn parse_glob(glob: &str) -> Result<UrlPattern, regex::Error> {
+  UrlPattern::parse(glob)
+}
+
+impl Args {
+  /// `config` with the command line's options applied on top.
+  fn crawl_config(&self, mut config: CrawlConfig, cancel: CancelToken) -> CrawlConfig {
+    if let Some(threads) = self.threads {
+      config.threads = threads;
+    }
+    config.max_depth = self.max_depth.or(config.max_depth);
+    config.max_pages = self.max_pages.or(config.max_pages);
+    config.include.extend(self.include.iter().cloned());
+    config.exclude.extend(self.exclude.iter().cloned());
+    if let Some(scope) = &self.scope {
+      config.scope = scope.clone();
+    }
+    if self.no_external {
+      config.check_external = false;
+    }
+    if let Some(timeout) = self.timeout {
+      config.limits.read_timeout = Duration::from_secs(timeout);
+    }
+    CrawlConfig { cancel, ..config }
+  }
+
+  /// Crawl the site in `--root` if there is one, the start URLs otherwise.
+  fn crawl(&self, config: CrawlConfig) -> Result<CrawlReport, Box<dyn std::error::Error>> {
+    match (&self.root, &self.site_url) {
+      (Some(root), Some(site_url)) => Ok(local_site::check_local_site(
+        root,
+        site_url.clone(),
+        config,
+      )?),
+      _ => Ok(super::check_sites(&self.start_urls, config)?),
+    }
+  }
+}
+
+/// The status to exit with once the report is written.
+fn exit_code(report: &CrawlReport) -> ExitCode {
+  if report.interrupted {
+    ExitCode::from(2)
+  } else if report.failures().next().is_some() {
+    ExitCode::from(1)
+  } else {
+    ExitCode::SUCCESS
+  }
+}
+
+/// Run the `link-checker` binary.
+pub fn main() -> ExitCode {
+  let args = Args::parse();
+  let file_config = match &args.config {
+    Some(path) => match CrawlConfig::from_file(path) {
+      Ok(config) => config,
+      Err(error) => {
+        eprintln!("{error}");
+        return ExitCode::from(2);
+      }
+    },
+    None => CrawlConfig::default(),
+  };
+  let cancel = CancelToken::default();
+  if let Err(error) = progress::cancel_on_signal(&cancel) {
+    eprintln!("Could not install the Ctrl-C handler: {error}");
+  }
+  let config = args.crawl_config(file_config, cancel);
+  let report = match args.crawl(config) {
+    Ok(report) => report,
+    Err(error) => {
+      eprintln!("Could not run the crawl: {error}");
+      return ExitCode::from(2);
+    }
+  };
+  let rendered = OutputFormat::from(args.format).render(&report);
+  match &args.output {
+    Some(path) => {
+      if let Err(error) = fs::write(path, rendered) {
+        eprintln!("Could not write {}: {error}", path.display());
+        return ExitCode::from(2);
+      }
+    }
+    None => print!("{rendered}"),
+  }
+  exit_code(&report)
+}
+
+#[cfg(test)]
+mod tests {
+  use reqwest::StatusCode;
+
+  use super::super::config_file;
+  use super::super::report::CheckedUrl;
+  use super::super::Error;
+  use super::*;
+
+  #[test]
+  fn test_arguments_map_onto_the_config() {
+    let args = Args::try_parse_from([
+      "link-checker",
+      "https://example.com/",
+      "https://docs.example.com/",
+      "-j",
+      "4",
+      "--max-depth",
+      "2",
+      "--exclude",
+      "https://example.com/legacy/*",
+      "--no-external",
+      "--scope",
+      "path=/docs/",
+      "--timeout",
+      "5",
+      "--format",
+      "sarif",
+    ])
+    .unwrap();
+    let config = args.crawl_config(CrawlConfig::default(), CancelToken::default());
+
+    assert_eq!(args.start_urls.len(), 2);
+    assert!(matches!(args.format, Format::Sarif));
+    assert_eq!(config.threads, 4);
+    assert_eq!(config.max_depth, Some(2));
+    assert!(!config.check_external);
+    assert_eq!(
+      config.scope,
+      ScopePolicy::PathPrefix(String::from("/docs/"))
+    );
+    assert_eq!(config.limits.read_timeout, Duration::from_secs(5));
+    let legacy = Url::parse("https://example.com/legacy/page").unwrap();
+    assert!(!config.is_in_scope(&legacy));
+  }
+
+  #[test]
+  fn test_command_line_overrides_the_config_file() {
+    let file_config = config_file::parse(
+      "threads = 2\nmax_depth = 5\nexclude = [\"/legacy/*\"]",
+      config_file::Format::Toml,
+      |_| None,
+    )
+    .unwrap();
+    let args =
+      Args::try_parse_from(["link-checker", "https://example.com/", "--max-depth", "1"]).unwrap();
+    let config = args.crawl_config(file_config, CancelToken::default());
+
+    assert_eq!(config.threads, 2);
+    assert_eq!(config.max_depth, Some(1));
+    assert_eq!(config.exclude.len(), 1);
+  }
+
+  #[test]
+  fn test_root_replaces_the_start_urls() {
+    let args = Args::try_parse_from([
+      "link-checker",
+      "--root",
+      "public",
+      "--site-url",
+      "https://docs.example.com/",
+    ])
+    .unwrap();
+
+    assert!(args.start_urls.is_empty());
+    assert_eq!(args.root, Some(PathBuf::from("public")));
+    assert_eq!(
+      args.site_url,
+      Some(Url::parse("https://docs.example.com/").unwrap())
+    );
+  }
+
+  #[test]
+  fn test_invalid_arguments_are_rejected() {
+    for args in [
+      vec!["link-checker"],
+      vec!["link-checker", "not a url"],
+      vec!["link-checker", "https://example.com/", "--threads", "0"],
+      vec!["link-checker", "https://example.com/", "--format", "xml"],
+      vec!["link-checker", "https://example.com/", "--scope", "hosts="],
+      vec!["link-checker", "--root", "public"],
+      vec!["link-checker", "--site-url", "https://example.com/"],
+      vec![
+        "link-checker",
+        "https://example.com/",
+        "--root",
+        "public",
+        "--site-url",
+        "https://example.com/",
+      ],
+    ] {
+      assert!(Args::try_parse_from(&args).is_err(), "{args:?}");
+    }
+  }
+
+  #[test]
+  fn test_broken_links_fail_the_run() {
+    let mut report = CrawlReport::default();
+    assert_eq!(exit_code(&report), ExitCode::SUCCESS);
+    report.checked_urls.push(CheckedUrl {
+      url: Url::parse("https://example.com/missing").unwrap(),
+      status: Some(StatusCode::NOT_FOUND),
+      redirect_chain: Vec::new(),
+      elapsed: Duration::ZERO,
+      sources: Vec::new(),
+      error: Some(Error::BadResponse {
+        status: StatusCode::NOT_FOUND,
+        retry_after: None,
+      }),
+    });
+    assert_eq!(exit_code(&report), ExitCode::from(1));
+    report.interrupted = true;
+    assert_eq!(exit_code(&report), ExitCode::from(2));
+  }
+}
@@ -0,0 +1,287 @@
+// Crawl bounds
+// Limits checked by `CrawlState` before a command is enqueued, so a crawl
+// of a large site stops at a known size instead of running until the
+// frontier happens to drain.
+use std::fmt;
+use std::time::Duration;
+
+use regex::Regex;
+use reqwest::header::HeaderMap;
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+
+use super::cache::CacheConfig;
+use super::checkpoint::CheckpointConfig;
+use super::extract::Extractor;
+use super::fetch::FetchLimits;
+use super::normalize::NormalizeRules;
+use super::progress::{CancelToken, Progress};
+use super::rate_limit::RateLimit;
+use super::redirect::RedirectPolicy;
+use super::retry::RetryPolicy;
+use super::scope::ScopePolicy;
+
+/// A pattern matched against the whole URL string.
+#[derive(Debug, Clone)]
+pub struct UrlPattern(Regex);
+
+impl UrlPattern {
+  /// `*` matches any run of characters and `?` a single one. Everything
+  /// else is literal, e.g. `https://example.com/blog/*`.
+  pub fn glob(glob: &str) -> Result<UrlPattern, regex::Error> {
+    Regex::new(&format!("^{}$", glob_to_regex(glob))).map(UrlPattern)
+  }
+
+  /// A regular expression, unanchored unless it says otherwise.
+  pub fn regex(regex: &str) -> Result<UrlPattern, regex::Error> {
+    Regex::new(regex).map(UrlPattern)
+  }
+
+  /// A glob as for `glob`, except that one starting with `/` is matched
+  /// against the path on any host, e.g. `/legacy/*`.
+  pub fn parse(pattern: &str) -> Result<UrlPattern, regex::Error> {
+    if !pattern.starts_with('/') {
+      return UrlPattern::glob(pattern);
+    }
+    let any_origin = format!("^[a-z][a-z0-9+.-]*://[^/]*{}$", glob_to_regex(pattern));
+    Regex::new(&any_origin).map(UrlPattern)
+  }
+
+  pub fn matches(&self, url: &Url) -> bool {
+    self.0.is_match(url.as_str())
+  }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+  let mut pattern = String::new();
+  for c in glob.chars() {
+    match c {
+      '*' => pattern.push_str(".*"),
+      '?' => pattern.push('.'),
+      c => pattern.push_str(&regex::escape(&c.to_string())),
+    }
+  }
+  pattern
+}
+
+/// Settings for the URLs matching a pattern.
+#[derive(Debug, Clone)]
+pub struct UrlRule {
+  pub pattern: UrlPattern,
+  /// Statuses that count as working besides 2xx, e.g. 403 for pages
+  /// behind a login.
+  pub accept: Vec<StatusCode>,
+  /// Replaces the read timeout for these URLs.
+  pub timeout: Option<Duration>,
+  /// Sent with every request for these URLs, on top of the crawler's own.
+  pub headers: HeaderMap,
+}
+
+impl UrlRule {
+  pub fn new(pattern: UrlPattern) -> UrlRule {
+    UrlRule {
+      pattern,
+      accept: Vec::new(),
+      timeout: None,
+      headers: HeaderMap::new(),
+    }
+  }
+}
+
+/// Per-URL overrides. Every rule whose pattern matches applies, and later
+/// rules win where they disagree.
+#[derive(Debug, Clone, Default)]
+pub struct UrlRules(pub Vec<UrlRule>);
+
+impl UrlRules {
+  fn matching<'a>(&'a self, url: &'a Url) -> impl DoubleEndedIterator<Item = &'a UrlRule> {
+    self.0.iter().filter(move |rule| rule.pattern.matches(url))
+  }
+
+  /// Whether `status` means the link at `url` works.
+  pub fn accepts(&self, url: &Url, status: StatusCode) -> bool {
+    status.is_success() || self.matching(url).any(|rule| rule.accept.contains(&status))
+  }
+
+  pub fn timeout(&self, url: &Url) -> Option<Duration> {
+    self.matching(url).rev().find_map(|rule| rule.timeout)
+  }
+
+  /// The extra request headers for `url`. They are looked up again for
+  /// every redirect hop, so they never follow a redirect to another site.
+  pub fn headers(&self, url: &Url) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for rule in self.matching(url) {
+      for (name, value) in &rule.headers {
+        headers.insert(name, value.clone());
+      }
+    }
+    headers
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct CrawlConfig {
+  /// How many links away from the start URL a page may be. Pages at this
+  /// depth are still checked, but their links are not followed.
+  pub max_depth: Option<usize>,
+  /// Total number of URLs to check, the start URL included.
+  pub max_pages: Option<usize>,
+  /// Number of URLs to check on any single host.
+  pub max_pages_per_host: Option<usize>,
+  /// When non-empty, only URLs matching one of these are checked.
+  pub include: Vec<UrlPattern>,
+  /// URLs matching any of these are never checked.
+  pub exclude: Vec<UrlPattern>,
+  /// Which URLs belong to the site, so their links are followed.
+  pub scope: ScopePolicy,
+  /// Whether links out of `scope` are checked.
+  pub check_external: bool,
+  /// Sitemaps whose pages are checked along with the start URL, so pages
+  /// that nothing links to are found too.
+  pub sitemaps: Vec<Url>,
+  /// What to look for in a page. Defaults to every kind of resource.
+  pub extractors: Vec<Extractor>,
+  /// How transient failures are retried.
+  pub retry: RetryPolicy,
+  /// Which spellings of a URL count as the same page.
+  pub normalize: NormalizeRules,
+  /// How far and where redirects are followed.
+  pub redirects: RedirectPolicy,
+  /// Worker threads making requests.
+  pub threads: u32,
+  /// Requests allowed per host, `None` for no limit.
+  pub rate_limit: Option<RateLimit>,
+  /// Timeouts for every request and the largest page body read.
+  pub limits: FetchLimits,
+  /// Accepted statuses, timeouts and headers for particular URLs.
+  pub rules: UrlRules,
+  /// Where and how often to save the crawl so it can be resumed.
+  pub checkpoint: Option<CheckpointConfig>,
+  /// Where to keep responses between runs, so unchanged pages are not
+  /// downloaded again.
+  pub cache: Option<CacheConfig>,
+  /// Where to send progress events while the crawl runs.
+  pub progress: Progress,
+  /// Cancels the crawl when set.
+  pub cancel: CancelToken,
+}
+
+impl Default for CrawlConfig {
+  fn default() -> Self {
+    CrawlConfig {
+      max_depth: None,
+      max_pages: None,
+      max_pages_per_host: None,
+      include: Vec::new(),
+      exclude: Vec::new(),
+      scope: ScopePolicy::default(),
+      check_external: true,
+      sitemaps: Vec::new(),
+      extractors: Extractor::defaults(),
+      retry: RetryPolicy::default(),
+      normalize: NormalizeRules::default(),
+      redirects: RedirectPolicy::default(),
+      threads: 16,
+      rate_limit: Some(RateLimit::default()),
+      limits: FetchLimits::default(),
+      rules: UrlRules::default(),
+      checkpoint: None,
+      cache: None,
+      progress: Progress::default(),
+      cancel: CancelToken::default(),
+    }
+  }
+}
+
+impl CrawlConfig {
+  /// Whether the include/exclude rules let `url` through.
+  pub fn is_in_scope(&self, url: &Url) -> bool {
+    let included = self.include.is_empty() || self.include.iter().any(|p| p.matches(url));
+    included && !self.exclude.iter().any(|p| p.matches(url))
+  }
+}
+
+/// Why a discovered URL was not checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SkipReason {
+  Excluded,
+  MaxPages,
+  MaxPagesPerHost,
+  External,
+}
+
+impl fmt::Display for SkipReason {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let reason = match self {
+      SkipReason::Excluded => "excluded by include/exclude rules",
+      SkipReason::MaxPages => "page budget exhausted",
+      SkipReason::MaxPagesPerHost => "page budget for the host exhausted",
+      SkipReason::External => "external link, external checks are off",
+    };
+    f.write_str(reason)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn url(url: &str) -> Url {
+    Url::parse(url).unwrap()
+  }
+
+  #[test]
+  fn test_glob_matches_whole_url() {
+    let pattern = UrlPattern::glob("https://example.com/blog/*").unwrap();
+    assert!(pattern.matches(&url("https://example.com/blog/2023/post")));
+    assert!(!pattern.matches(&url("https://example.com/about")));
+    // Dots are literal, not "any character".
+    let pattern = UrlPattern::glob("https://example.com/*.pdf").unwrap();
+    assert!(!pattern.matches(&url("https://example.com/apdf")));
+  }
+
+  #[test]
+  fn test_exclude_wins_over_include() {
+    let config = CrawlConfig {
+      include: vec![UrlPattern::glob("https://example.com/*").unwrap()],
+      exclude: vec![UrlPattern::regex(r"/legacy/").unwrap()],
+      ..CrawlConfig::default()
+    };
+    assert!(config.is_in_scope(&url("https://example.com/docs")));
+    assert!(!config.is_in_scope(&url("https://example.com/legacy/docs")));
+    assert!(!config.is_in_scope(&url("https://other.example/docs")));
+  }
+
+  #[test]
+  fn test_path_patterns_match_any_host() {
+    let pattern = UrlPattern::parse("/legacy/*").unwrap();
+    assert!(pattern.matches(&url("https://example.com/legacy/page")));
+    assert!(pattern.matches(&url("http://localhost:8080/legacy/")));
+    assert!(!pattern.matches(&url("https://example.com/docs/legacy/page")));
+  }
+
+  #[test]
+  fn test_later_rules_win() {
+    let rule = |pattern, timeout: u64, accept: u16| UrlRule {
+      accept: vec![StatusCode::from_u16(accept).unwrap()],
+      timeout: Some(Duration::from_secs(timeout)),
+      ..UrlRule::new(UrlPattern::parse(pattern).unwrap())
+    };
+    let rules = UrlRules(vec![
+      rule("https://example.com/*", 5, 401),
+      rule("/members/*", 60, 403),
+    ]);
+    let members = url("https://example.com/members/area");
+
+    assert_eq!(rules.timeout(&members), Some(Duration::from_secs(60)));
+    assert_eq!(
+      rules.timeout(&url("https://example.com/")),
+      Some(Duration::from_secs(5))
+    );
+    assert_eq!(rules.timeout(&url("https://example.org/")), None);
+    assert!(rules.accepts(&members, StatusCode::FORBIDDEN));
+    assert!(rules.accepts(&members, StatusCode::UNAUTHORIZED));
+    assert!(!rules.accepts(&url("https://example.com/"), StatusCode::FORBIDDEN));
+  }
+}
@@ -0,0 +1,390 @@
+// Config files
+// A crawl described in TOML or JSON instead of code, so each site can keep
+// its rules next to it. Everything is validated while loading, and a
+// mistake is reported with the file and the setting it is in:
+//
+//   max_depth = 3
+//   exclude = ["/legacy/*"]
+//   scope = "subdomains"
+//
+//   [[rules]]
+//   pattern = "/members/*"
+//   accept = [403]
+//
+//   [[rules]]
+//   pattern = "https://partner.example/*"
+//   timeout_secs = 60
+//
+//   [[rules]]
+//   pattern = "https://staging.example.com/*"
+//   headers = { Authorization = "Bearer ${STAGING_TOKEN}" }
+//
+// Header values can refer to environment variables as `${NAME}`, so tokens
+// stay out of the file.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::config::{CrawlConfig, UrlPattern, UrlRule, UrlRules};
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+  #[error("could not read {}: {source}", path.display())]
+  Read { path: PathBuf, source: io::Error },
+  #[error("{}: expected a .toml or .json file", path.display())]
+  UnknownFormat { path: PathBuf },
+  #[error("{}: {error}", path.display())]
+  Parse { path: PathBuf, error: ParseError },
+}
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+  /// Not TOML or JSON, or settings that do not exist.
+  #[error("{0}")]
+  Syntax(String),
+  #[error("{setting}: {message}")]
+  Invalid { setting: String, message: String },
+}
+
+fn invalid(setting: &str, message: impl Into<String>) -> ParseError {
+  ParseError::Invalid {
+    setting: setting.to_string(),
+    message: message.into(),
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+  Toml,
+  Json,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+  max_depth: Option<usize>,
+  max_pages: Option<usize>,
+  max_pages_per_host: Option<usize>,
+  threads: Option<u32>,
+  check_external: Option<bool>,
+  include: Vec<String>,
+  exclude: Vec<String>,
+  scope: Option<String>,
+  timeout_secs: Option<f64>,
+  rules: Vec<RuleFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+  pattern: String,
+  #[serde(default)]
+  accept: Vec<u16>,
+  timeout_secs: Option<f64>,
+  #[serde(default)]
+  headers: BTreeMap<String, String>,
+  /// Never check matching URLs, as if they were excluded.
+  #[serde(default)]
+  ignore: bool,
+}
+
+impl CrawlConfig {
+  /// Read a config file, picking the format from its extension. The
+  /// threaded and async crawlers and the command line all load their
+  /// settings through this.
+  pub fn from_file(path: &Path) -> Result<CrawlConfig, ConfigError> {
+    let format = match path.extension().and_then(|extension| extension.to_str()) {
+      Some("toml") => Format::Toml,
+      Some("json") => Format::Json,
+      _ => {
+        return Err(ConfigError::UnknownFormat {
+          path: path.to_path_buf(),
+        })
+      }
+    };
+    let text = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+      path: path.to_path_buf(),
+      source,
+    })?;
+    parse(&text, format, |name| std::env::var(name).ok()).map_err(|error| ConfigError::Parse {
+      path: path.to_path_buf(),
+      error,
+    })
+  }
+
+  /// Like `from_file`, but a file that does not exist stands for the
+  /// defaults.
+  pub fn from_file_or_default(path: &Path) -> Result<CrawlConfig, ConfigError> {
+    match CrawlConfig::from_file(path) {
+      Err(ConfigError::Read { source, .. }) if source.kind() == io::ErrorKind::NotFound => {
+        Ok(CrawlConfig::default())
+      }
+      result => result,
+    }
+  }
+}
+
+/// Parse a config, looking `${NAME}` references up with `env`.
+pub fn parse(
+  text: &str,
+  format: Format,
+  env: impl Fn(&str) -> Option<String>,
+) -> Result<CrawlConfig, ParseError> {
+  let file: ConfigFile = match format {
+    Format::Toml => toml::from_str(text).map_err(|error| error.to_string()),
+    Format::Json => serde_json::from_str(text).map_err(|error| error.to_string()),
+  }
+  .map_err(ParseError::Syntax)?;
+  file.into_config(&env)
+}
+
+impl ConfigFile {
+  fn into_config(self, env: &dyn Fn(&str) -> Option<String>) -> Result<CrawlConfig, ParseError> {
+    let defaults = CrawlConfig::default();
+    if self.threads == Some(0) {
+      return Err(invalid("threads", "must be at least 1"));
+    }
+    let mut limits = defaults.limits;
+    if let Some(seconds) = self.timeout_secs {
+      limits.read_timeout = timeout("timeout_secs", seconds)?;
+    }
+    let mut config = CrawlConfig {
+      max_depth: self.max_depth,
+      max_pages: self.max_pages,
+      max_pages_per_host: self.max_pages_per_host,
+      threads: self.threads.unwrap_or(defaults.threads),
+      check_external: self.check_external.unwrap_or(defaults.check_external),
+      include: patterns("include", &self.include)?,
+      exclude: patterns("exclude", &self.exclude)?,
+      scope: match &self.scope {
+        Some(scope) => scope
+          .parse()
+          .map_err(|message: String| invalid("scope", message))?,
+        None => defaults.scope.clone(),
+      },
+      limits,
+      ..defaults
+    };
+    let mut rules = Vec::new();
+    for (index, rule) in self.rules.into_iter().enumerate() {
+      let setting = |name: &str| format!("rules[{index}].{name}");
+      let pattern = pattern(&setting("pattern"), &rule.pattern)?;
+      if rule.ignore {
+        config.exclude.push(pattern);
+        continue;
+      }
+      let mut url_rule = UrlRule::new(pattern);
+      for status in rule.accept {
+        let status = StatusCode::from_u16(status).map_err(|_| {
+          invalid(
+            &setting("accept"),
+            format!("{status} is not an HTTP status"),
+          )
+        })?;
+        url_rule.accept.push(status);
+      }
+      if let Some(seconds) = rule.timeout_secs {
+        url_rule.timeout = Some(timeout(&setting("timeout_secs"), seconds)?);
+      }
+      for (name, value) in rule.headers {
+        let setting = setting(&format!("headers.{name}"));
+        let header_name = HeaderName::from_bytes(name.as_bytes())
+          .map_err(|_| invalid(&setting, "not a valid header name"))?;
+        let value = expand_env(&value, env).map_err(|message| invalid(&setting, message))?;
+        let header_value = HeaderValue::from_str(&value)
+          .map_err(|_| invalid(&setting, "not a valid header value"))?;
+        url_rule.headers.insert(header_name, header_value);
+      }
+      rules.push(url_rule);
+    }
+    config.rules = UrlRules(rules);
+    Ok(config)
+  }
+}
+
+fn pattern(setting: &str, pattern: &str) -> Result<UrlPattern, ParseError> {
+  UrlPattern::parse(pattern).map_err(|error| invalid(setting, error.to_string()))
+}
+
+fn patterns(setting: &str, patterns: &[String]) -> Result<Vec<UrlPattern>, ParseError> {
+  patterns
+    .iter()
+    .enumerate()
+    .map(|(index, p)| pattern(&format!("{setting}[{index}]"), p))
+    .collect()
+}
+
+fn timeout(setting: &str, seconds: f64) -> Result<Duration, ParseError> {
+  Duration::try_from_secs_f64(seconds)
+    .ok()
+    .filter(|timeout| !timeout.is_zero())
+    .ok_or_else(|| {
+      invalid(
+        setting,
+        format!("{seconds} is not a positive number of seconds"),
+      )
+    })
+}
+
+/// Replace every `${NAME}` in `value` with that environment variable.
+fn expand_env(value: &str, env: &dyn Fn(&str) -> Option<String>) -> Result<String, String> {
+  let mut expanded = String::new();
+  let mut rest = value;
+  while let Some(start) = rest.find("${") {
+    expanded.push_str(&rest[..start]);
+    let Some(length) = rest[start + 2..].find('}') else {
+      return Err(String::from("unterminated ${...}"));
+    };
+    let name = &rest[start + 2..start + 2 + length];
+    let Some(variable) = env(name) else {
+      return Err(format!("environment variable {name} is not set"));
+    };
+    expanded.push_str(&variable);
+    rest = &rest[start + 2 + length + 1..];
+  }
+  expanded.push_str(rest);
+  Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+  use reqwest::header;
+  use reqwest::Url;
+
+  use super::super::scope::ScopePolicy;
+  use super::*;
+
+  fn url(url: &str) -> Url {
+    Url::parse(url).unwrap()
+  }
+
+  fn env(name: &str) -> Option<String> {
+    (name == "STAGING_TOKEN").then(|| String::from("secret"))
+  }
+
+  #[test]
+  fn test_toml_and_json_give_the_same_config() {
+    let toml = r#"
+      max_depth = 3
+      threads = 4
+      exclude = ["/legacy/*"]
+      scope = "subdomains"
+
+      [[rules]]
+      pattern = "/members/*"
+      accept = [403]
+
+      [[rules]]
+      pattern = "https://partner.example/*"
+      timeout_secs = 60
+
+      [[rules]]
+      pattern = "https://staging.example.com/*"
+      headers = { Authorization = "Bearer ${STAGING_TOKEN}" }
+    "#;
+    let json = r#"{
+      "max_depth": 3,
+      "threads": 4,
+      "exclude": ["/legacy/*"],
+      "scope": "subdomains",
+      "rules": [
+        { "pattern": "/members/*", "accept": [403] },
+        { "pattern": "https://partner.example/*", "timeout_secs": 60 },
+        {
+          "pattern": "https://staging.example.com/*",
+          "headers": { "Authorization": "Bearer ${STAGING_TOKEN}" }
+        }
+      ]
+    }"#;
+
+    for config in [
+      parse(toml, Format::Toml, env).unwrap(),
+      parse(json, Format::Json, env).unwrap(),
+    ] {
+      assert_eq!(config.max_depth, Some(3));
+      assert_eq!(config.threads, 4);
+      assert_eq!(config.scope, ScopePolicy::Subdomains);
+      assert!(!config.is_in_scope(&url("https://example.com/legacy/a")));
+      assert!(config
+        .rules
+        .accepts(&url("https://example.com/members/"), StatusCode::FORBIDDEN));
+      assert_eq!(
+        config.rules.timeout(&url("https://partner.example/api")),
+        Some(Duration::from_secs(60))
+      );
+      let headers = config.rules.headers(&url("https://staging.example.com/"));
+      assert_eq!(headers[header::AUTHORIZATION], "Bearer secret");
+    }
+  }
+
+  #[test]
+  fn test_ignore_rules_exclude_urls() {
+    let config = parse(
+      "[[rules]]\npattern = \"/drafts/*\"\nignore = true",
+      Format::Toml,
+      env,
+    )
+    .unwrap();
+    assert!(!config.is_in_scope(&url("https://example.com/drafts/a")));
+    assert!(config.rules.0.is_empty());
+  }
+
+  #[test]
+  fn test_mistakes_name_the_setting() {
+    let invalid = |toml: &str| match parse(toml, Format::Toml, env) {
+      Err(error @ ParseError::Invalid { .. }) => error.to_string(),
+      other => panic!("{toml}: {other:?}"),
+    };
+    assert_eq!(
+      invalid("[[rules]]\npattern = \"/a\"\naccept = [1000]"),
+      "rules[0].accept: 1000 is not an HTTP status"
+    );
+    assert_eq!(
+      invalid("[[rules]]\npattern = \"/a\"\ntimeout_secs = 0"),
+      "rules[0].timeout_secs: 0 is not a positive number of seconds"
+    );
+    assert_eq!(
+      invalid("[[rules]]\npattern = \"/a\"\nheaders = { Authorization = \"${MISSING}\" }"),
+      "rules[0].headers.Authorization: environment variable MISSING is not set"
+    );
+    assert_eq!(invalid("threads = 0"), "threads: must be at least 1");
+    assert_eq!(
+      invalid("scope = \"path=docs\""),
+      "scope: \"path=docs\" needs a path starting with /"
+    );
+
+    let syntax = parse("max_dpeth = 3", Format::Toml, env).unwrap_err();
+    assert!(matches!(syntax, ParseError::Syntax(_)));
+    assert!(syntax.to_string().contains("max_dpeth"));
+  }
+
+  #[test]
+  fn test_load_reports_the_file() {
+    let path =
+      std::env::temp_dir().join(format!("link-checker-config-{}.toml", std::process::id()));
+    fs::write(&path, "max_pages = -1").unwrap();
+    let error = CrawlConfig::from_file(&path).unwrap_err();
+    fs::remove_file(&path).unwrap();
+
+    assert!(error
+      .to_string()
+      .starts_with(&format!("{}: ", path.display())));
+    assert!(matches!(
+      CrawlConfig::from_file(Path::new("link-checker.yaml")),
+      Err(ConfigError::UnknownFormat { .. })
+    ));
+    let missing = std::env::temp_dir().join("link-checker-no-such-config.toml");
+    assert!(CrawlConfig::from_file(&missing).is_err());
+    assert_eq!(
+      CrawlConfig::from_file_or_default(&missing).unwrap().threads,
+      CrawlConfig::default().threads
+    );
+  }
+}
@@ -0,0 +1,282 @@
+// Link extraction
+// Every reference a page makes is found by an `Extractor`: a CSS selector,
+// the attribute holding the URL and how to read that attribute. Each one is
+// tagged with a `ResourceKind` so a broken stylesheet can be told apart from
+// a broken hyperlink.
+use std::fmt;
+
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+
+use super::{Link, LinkSource};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ResourceKind {
+  Hyperlink,
+  Image,
+  Script,
+  Stylesheet,
+  Frame,
+  Canonical,
+  MetaRefresh,
+  /// A `<loc>` in a sitemap rather than a link on a page.
+  SitemapEntry,
+}
+
+impl ResourceKind {
+  /// Whether a resource of this kind is an HTML page whose own links are
+  /// worth following. Images and scripts are only checked.
+  pub fn is_document(&self) -> bool {
+    matches!(
+      self,
+      ResourceKind::Hyperlink
+        | ResourceKind::Frame
+        | ResourceKind::Canonical
+        | ResourceKind::MetaRefresh
+        | ResourceKind::SitemapEntry
+    )
+  }
+
+  pub fn id(&self) -> &'static str {
+    match self {
+      ResourceKind::Hyperlink => "hyperlink",
+      ResourceKind::Image => "image",
+      ResourceKind::Script => "script",
+      ResourceKind::Stylesheet => "stylesheet",
+      ResourceKind::Frame => "frame",
+      ResourceKind::Canonical => "canonical",
+      ResourceKind::MetaRefresh => "meta_refresh",
+      ResourceKind::SitemapEntry => "sitemap_entry",
+    }
+  }
+}
+
+impl fmt::Display for ResourceKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      ResourceKind::Hyperlink => "hyperlink",
+      ResourceKind::Image => "image",
+      ResourceKind::Script => "script",
+      ResourceKind::Stylesheet => "stylesheet",
+      ResourceKind::Frame => "frame",
+      ResourceKind::Canonical => "canonical link",
+      ResourceKind::MetaRefresh => "meta refresh",
+      ResourceKind::SitemapEntry => "sitemap entry",
+    };
+    f.write_str(name)
+  }
+}
+
+/// How the URLs are written in the attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeSyntax {
+  /// The whole value is one URL.
+  Url,
+  /// `srcset`: comma separated candidates, each a URL and a descriptor.
+  Srcset,
+  /// `<meta http-equiv=refresh>` content: `5; url=/next`.
+  MetaRefresh,
+}
+
+#[derive(Debug, Clone)]
+pub struct Extractor {
+  pub kind: ResourceKind,
+  pub selector: Selector,
+  pub attribute: String,
+  pub syntax: AttributeSyntax,
+}
+
+impl Extractor {
+  pub fn new(
+    kind: ResourceKind,
+    selector: &str,
+    attribute: &str,
+    syntax: AttributeSyntax,
+  ) -> Result<Extractor, String> {
+    let selector =
+      Selector::parse(selector).map_err(|err| format!("bad selector {selector:?}: {err}"))?;
+    Ok(Extractor {
+      kind,
+      selector,
+      attribute: attribute.to_string(),
+      syntax,
+    })
+  }
+
+  /// Every kind of reference a browser would load or follow.
+  pub fn defaults() -> Vec<Extractor> {
+    use AttributeSyntax::{MetaRefresh, Srcset, Url};
+    let extractors = [
+      (ResourceKind::Hyperlink, "a[href]", "href", Url),
+      (ResourceKind::Image, "img[src]", "src", Url),
+      (
+        ResourceKind::Image,
+        "img[srcset], source[srcset]",
+        "srcset",
+        Srcset,
+      ),
+      (ResourceKind::Script, "script[src]", "src", Url),
+      (
+        ResourceKind::Stylesheet,
+        "link[rel~=stylesheet i][href]",
+        "href",
+        Url,
+      ),
+      (
+        ResourceKind::Canonical,
+        "link[rel~=canonical i][href]",
+        "href",
+        Url,
+      ),
+      (ResourceKind::Frame, "iframe[src], frame[src]", "src", Url),
+      (
+        ResourceKind::MetaRefresh,
+        "meta[http-equiv=refresh i][content]",
+        "content",
+        MetaRefresh,
+      ),
+    ];
+    extractors
+      .into_iter()
+      .map(|(kind, selector, attribute, syntax)| {
+        Extractor::new(kind, selector, attribute, syntax).unwrap()
+      })
+      .collect()
+  }
+}
+
+/// Split an attribute value into the URL references it holds.
+fn references(value: &str, syntax: AttributeSyntax) -> Vec<&str> {
+  match syntax {
+    AttributeSyntax::Url => vec![value.trim()],
+    AttributeSyntax::Srcset => value
+      .split(',')
+      .filter_map(|candidate| candidate.split_whitespace().next())
+      .collect(),
+    AttributeSyntax::MetaRefresh => {
+      let Some((_, target)) = value.split_once(';') else {
+        return Vec::new();
+      };
+      let target = target.trim();
+      let Some(prefix) = target.get(..3) else {
+        return Vec::new();
+      };
+      if !prefix.eq_ignore_ascii_case("url") {
+        return Vec::new();
+      }
+      let Some(target) = target[3..].trim_start().strip_prefix('=') else {
+        return Vec::new();
+      };
+      vec![target.trim().trim_matches(|c| c == '\'' || c == '"')]
+    }
+  }
+}
+
+/// Run every extractor over the document and resolve what they find
+/// against `base_url`.
+pub fn extract_links(base_url: &Url, document: &Html, extractors: &[Extractor]) -> Vec<Link> {
+  let mut links = Vec::new();
+  for extractor in extractors {
+    for element in document.select(&extractor.selector) {
+      let Some(value) = element.value().attr(&extractor.attribute) else {
+        continue;
+      };
+      for reference in references(value, extractor.syntax) {
+        if reference.is_empty() {
+          continue;
+        }
+        match base_url.join(reference) {
+          Ok(url) => {
+            let text = element.text().collect::<Vec<_>>().join(" ");
+            let source = LinkSource {
+              page: base_url.clone(),
+              kind: extractor.kind,
+              element: element.value().name().to_string(),
+              text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+            };
+            links.push(Link { url, source });
+          }
+          Err(err) => {
+            eprintln!("On {base_url:#}: ignored unparsable {reference:?}: {err}");
+          }
+        }
+      }
+    }
+  }
+  links
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn found(body: &str) -> Vec<(ResourceKind, String)> {
+    let base_url = Url::parse("https://example.com/dir/page.html").unwrap();
+    let document = Html::parse_document(body);
+    extract_links(&base_url, &document, &Extractor::defaults())
+      .into_iter()
+      .map(|link| (link.source.kind, link.url.to_string()))
+      .collect()
+  }
+
+  #[test]
+  fn test_every_resource_kind_is_found() {
+    let body = r#"<html><head>
+      <link rel="Stylesheet" href="/site.css">
+      <link rel="canonical" href="https://example.com/page">
+      <link rel="preconnect" href="https://cdn.example.com">
+      <meta http-equiv="Refresh" content="5; URL='next.html'">
+      <script src="app.js"></script>
+    </head><body>
+      <a href="other.html">Other</a>
+      <img src="a.png" srcset="a-1x.png 1x, a-2x.png 2x">
+      <iframe src="https://video.example.com/embed"></iframe>
+    </body></html>"#;
+
+    let links = found(body);
+
+    assert!(links.contains(&(
+      ResourceKind::Stylesheet,
+      String::from("https://example.com/site.css")
+    )));
+    assert!(links.contains(&(
+      ResourceKind::Canonical,
+      String::from("https://example.com/page")
+    )));
+    assert!(links.contains(&(
+      ResourceKind::MetaRefresh,
+      String::from("https://example.com/dir/next.html")
+    )));
+    assert!(links.contains(&(
+      ResourceKind::Script,
+      String::from("https://example.com/dir/app.js")
+    )));
+    assert!(links.contains(&(
+      ResourceKind::Hyperlink,
+      String::from("https://example.com/dir/other.html")
+    )));
+    assert!(links.contains(&(
+      ResourceKind::Image,
+      String::from("https://example.com/dir/a-2x.png")
+    )));
+    assert!(links.contains(&(
+      ResourceKind::Frame,
+      String::from("https://video.example.com/embed")
+    )));
+    // The seven above plus a.png and a-1x.png. `preconnect` is not a reference.
+    assert_eq!(links.len(), 9);
+  }
+
+  #[test]
+  fn test_meta_refresh_without_url_is_ignored() {
+    assert_eq!(
+      references("30", AttributeSyntax::MetaRefresh),
+      Vec::<&str>::new()
+    );
+    assert_eq!(
+      references("0;url=/a", AttributeSyntax::MetaRefresh),
+      vec!["/a"]
+    );
+  }
+}
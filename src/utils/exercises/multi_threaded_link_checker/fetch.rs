@@ -0,0 +1,484 @@
+// HTTP transport
+// Every request the crawler makes, robots.txt included, goes through a
+// `Fetcher`. Fetchers answer a single request and leave redirects to the
+// caller. `HttpFetcher` talks to the network; `MemoryFetcher`, built only
+// for tests, serves a site described in code, so crawl logic can be tested
+// without sockets.
+// Bodies are only read on demand and never past a size limit, and every
+// network request is bounded by `FetchLimits`.
+#[cfg(test)]
+use std::collections::HashMap;
+use std::io::{self, Read};
+#[cfg(test)]
+use std::sync::Mutex;
+use std::time::Duration;
+
+use reqwest::header::{self, HeaderMap};
+#[cfg(test)]
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::{blocking::Client, redirect, Method, StatusCode, Url};
+
+use super::config::UrlRules;
+use super::{Error, USER_AGENT};
+
+/// How long a request may take and how much of a page is read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FetchLimits {
+  /// Time allowed to open a connection.
+  pub connect_timeout: Duration,
+  /// Time allowed for the whole request, reading the body included.
+  pub read_timeout: Duration,
+  /// Largest page body read, in bytes. Larger pages fail with
+  /// `BodyTooLarge` instead of being parsed.
+  pub max_body_size: u64,
+}
+
+impl Default for FetchLimits {
+  fn default() -> Self {
+    FetchLimits {
+      connect_timeout: Duration::from_secs(10),
+      read_timeout: Duration::from_secs(30),
+      max_body_size: 10 * 1024 * 1024,
+    }
+  }
+}
+
+pub trait Fetcher: Send + Sync {
+  /// GET `url` with extra request `headers`, without following redirects.
+  /// Non-2xx statuses are returned as responses, only failing to get any
+  /// answer is an error.
+  fn get(&self, url: &Url, headers: &HeaderMap) -> Result<Response, Error>;
+
+  /// Like `get`, but asking for the headers only. Fetchers that cannot
+  /// send HEAD requests answer with a GET.
+  fn head(&self, url: &Url, headers: &HeaderMap) -> Result<Response, Error> {
+    self.get(url, headers)
+  }
+
+  /// Whether fetching `url` goes over the network. Only remote requests
+  /// are subject to robots.txt and rate limits.
+  fn is_remote(&self, _url: &Url) -> bool {
+    true
+  }
+}
+
+/// A response whose body has not been read yet, so callers only pay for the
+/// body when they need it.
+pub struct Response {
+  pub url: Url,
+  pub status: StatusCode,
+  pub headers: HeaderMap,
+  body: Box<dyn Body>,
+}
+
+impl Response {
+  /// A response with a body already in memory.
+  pub fn new(
+    url: Url,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: impl Into<Vec<u8>>,
+  ) -> Response {
+    Response {
+      url,
+      status,
+      headers,
+      body: Box::new(body.into()),
+    }
+  }
+
+  /// The body as text, invalid UTF-8 replaced. Fails with `BodyTooLarge`
+  /// rather than reading more than `limit` bytes.
+  pub fn text(self, limit: u64) -> Result<String, Error> {
+    let bytes = self.bytes(limit)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+  }
+
+  /// The body, failing with `BodyTooLarge` rather than reading more than
+  /// `limit` bytes. A `Content-Length` over the limit fails before anything
+  /// is read.
+  pub fn bytes(self, limit: u64) -> Result<Vec<u8>, Error> {
+    check_content_length(&self.headers, limit)?;
+    let bytes = self.body.read_at_most(limit.saturating_add(1))?;
+    if bytes.len() as u64 > limit {
+      return Err(Error::BodyTooLarge(limit));
+    }
+    Ok(bytes)
+  }
+}
+
+/// Fail with `BodyTooLarge` if the response announces a body over `limit`
+/// bytes, before any of it is read.
+pub fn check_content_length(headers: &HeaderMap, limit: u64) -> Result<(), Error> {
+  let length = headers
+    .get(header::CONTENT_LENGTH)
+    .and_then(|length| length.to_str().ok())
+    .and_then(|length| length.parse::<u64>().ok());
+  if length.is_some_and(|length| length > limit) {
+    return Err(Error::BodyTooLarge(limit));
+  }
+  Ok(())
+}
+
+trait Body: Send {
+  /// Read up to `limit` bytes.
+  fn read_at_most(self: Box<Self>, limit: u64) -> Result<Vec<u8>, Error>;
+}
+
+/// A body still on the wire.
+struct HttpBody {
+  response: reqwest::blocking::Response,
+  read_timeout: Duration,
+}
+
+impl Body for HttpBody {
+  fn read_at_most(self: Box<Self>, limit: u64) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    let read_timeout = self.read_timeout;
+    self
+      .response
+      .take(limit)
+      .read_to_end(&mut bytes)
+      .map_err(|error| {
+        if is_timeout(&error) {
+          Error::ReadTimeout(read_timeout)
+        } else {
+          Error::Io(error)
+        }
+      })?;
+    Ok(bytes)
+  }
+}
+
+/// reqwest reports a body read that timed out as an I/O error wrapping its
+/// own error.
+fn is_timeout(error: &io::Error) -> bool {
+  error.kind() == io::ErrorKind::TimedOut
+    || error
+      .get_ref()
+      .and_then(|inner| inner.downcast_ref::<reqwest::Error>())
+      .is_some_and(|inner| inner.is_timeout())
+}
+
+impl Body for Vec<u8> {
+  fn read_at_most(mut self: Box<Self>, limit: u64) -> Result<Vec<u8>, Error> {
+    self.truncate(usize::try_from(limit).unwrap_or(usize::MAX));
+    Ok(*self)
+  }
+}
+
+/// Fetches over the network with a blocking reqwest client. The client is
+/// shared by every worker, and with it the connection pool. Requests get
+/// the headers and timeout their URL's rules ask for.
+pub struct HttpFetcher {
+  client: Client,
+  limits: FetchLimits,
+  rules: UrlRules,
+}
+
+impl HttpFetcher {
+  /// Fails only if the client cannot be set up, such as when no TLS backend
+  /// can be initialised.
+  pub fn new(limits: &FetchLimits, rules: &UrlRules) -> Result<HttpFetcher, reqwest::Error> {
+    let client = Client::builder()
+      .user_agent(USER_AGENT)
+      .redirect(redirect::Policy::none())
+      .connect_timeout(limits.connect_timeout)
+      .timeout(limits.read_timeout)
+      .build()?;
+    Ok(HttpFetcher {
+      client,
+      limits: *limits,
+      rules: rules.clone(),
+    })
+  }
+
+  fn send(&self, method: Method, url: &Url, headers: &HeaderMap) -> Result<Response, Error> {
+    let read_timeout = self.rules.timeout(url).unwrap_or(self.limits.read_timeout);
+    let request = self
+      .client
+      .request(method, url.clone())
+      .headers(self.rules.headers(url))
+      .headers(headers.clone())
+      .timeout(read_timeout);
+    let response = request.send().map_err(|error| {
+      if !error.is_timeout() {
+        Error::Reqwest(error)
+      } else if error.is_connect() {
+        Error::ConnectTimeout(self.limits.connect_timeout)
+      } else {
+        Error::ReadTimeout(read_timeout)
+      }
+    })?;
+    Ok(Response {
+      url: response.url().clone(),
+      status: response.status(),
+      headers: response.headers().clone(),
+      body: Box::new(HttpBody {
+        response,
+        read_timeout,
+      }),
+    })
+  }
+}
+
+impl Fetcher for HttpFetcher {
+  fn get(&self, url: &Url, headers: &HeaderMap) -> Result<Response, Error> {
+    self.send(Method::GET, url, headers)
+  }
+
+  fn head(&self, url: &Url, headers: &HeaderMap) -> Result<Response, Error> {
+    self.send(Method::HEAD, url, headers)
+  }
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone)]
+struct MemoryPage {
+  status: StatusCode,
+  headers: HeaderMap,
+  body: Vec<u8>,
+}
+
+/// An in-memory site. URLs without a page answer 404, and every request is
+/// logged so tests can tell how often a URL was fetched. Conditional
+/// requests matching a page's `ETag` or `Last-Modified` answer 304.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemoryFetcher {
+  pages: HashMap<String, MemoryPage>,
+  requests: Mutex<Vec<(Method, Url)>>,
+  refuse_head: bool,
+}
+
+#[cfg(test)]
+impl MemoryFetcher {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Serve `body` as HTML with a 200 at `url`.
+  pub fn page(self, url: &str, body: &str) -> Self {
+    self.response(url, 200, &[("Content-Type", "text/html")], body)
+  }
+
+  pub fn response(self, url: &str, status: u16, headers: &[(&str, &str)], body: &str) -> Self {
+    self.bytes(url, status, headers, body.as_bytes())
+  }
+
+  /// Like `response`, for a body that is not text.
+  pub fn bytes(mut self, url: &str, status: u16, headers: &[(&str, &str)], body: &[u8]) -> Self {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+      header_map.append(
+        HeaderName::from_bytes(name.as_bytes()).unwrap(),
+        HeaderValue::from_str(value).unwrap(),
+      );
+    }
+    let page = MemoryPage {
+      status: StatusCode::from_u16(status).unwrap(),
+      headers: header_map,
+      body: body.to_vec(),
+    };
+    self.pages.insert(Self::key(url), page);
+    self
+  }
+
+  /// Redirect `from` to `to`, which may be relative.
+  pub fn redirect(self, from: &str, status: u16, to: &str) -> Self {
+    self.response(from, status, &[("Location", to)], "")
+  }
+
+  /// Answer every HEAD request with 405, as some servers do.
+  pub fn refuse_head(mut self) -> Self {
+    self.refuse_head = true;
+    self
+  }
+
+  /// Every URL requested so far, in order, whatever the method.
+  pub fn requests(&self) -> Vec<Url> {
+    let requests = self.requests.lock().unwrap();
+    requests.iter().map(|(_, url)| url.clone()).collect()
+  }
+
+  /// The URLs requested with HEAD so far, in order.
+  pub fn head_requests(&self) -> Vec<Url> {
+    let requests = self.requests.lock().unwrap();
+    requests
+      .iter()
+      .filter(|(method, _)| *method == Method::HEAD)
+      .map(|(_, url)| url.clone())
+      .collect()
+  }
+
+  fn key(url: &str) -> String {
+    Url::parse(url).unwrap().as_str().to_string()
+  }
+
+  fn lookup(&self, method: Method, url: &Url) -> MemoryPage {
+    self.requests.lock().unwrap().push((method, url.clone()));
+    let mut without_fragment = url.clone();
+    without_fragment.set_fragment(None);
+    self
+      .pages
+      .get(without_fragment.as_str())
+      .cloned()
+      .unwrap_or_else(|| MemoryPage {
+        status: StatusCode::NOT_FOUND,
+        headers: HeaderMap::new(),
+        body: b"not found".to_vec(),
+      })
+  }
+}
+
+#[cfg(test)]
+impl MemoryFetcher {
+  fn answer(&self, method: Method, url: &Url, headers: &HeaderMap) -> Response {
+    let head = method == Method::HEAD;
+    if head && self.refuse_head {
+      self.lookup(method, url);
+      return Response::new(
+        url.clone(),
+        StatusCode::METHOD_NOT_ALLOWED,
+        HeaderMap::new(),
+        Vec::new(),
+      );
+    }
+    let page = self.lookup(method, url);
+    let validators = [
+      (header::IF_NONE_MATCH, header::ETAG),
+      (header::IF_MODIFIED_SINCE, header::LAST_MODIFIED),
+    ];
+    let not_modified = validators.iter().any(|(condition, validator)| {
+      headers
+        .get(condition)
+        .is_some_and(|value| page.headers.get(validator) == Some(value))
+    });
+    if page.status.is_success() && not_modified {
+      return Response::new(
+        url.clone(),
+        StatusCode::NOT_MODIFIED,
+        page.headers,
+        Vec::new(),
+      );
+    }
+    let body = if head { Vec::new() } else { page.body };
+    Response::new(url.clone(), page.status, page.headers, body)
+  }
+}
+
+#[cfg(test)]
+impl Fetcher for MemoryFetcher {
+  fn get(&self, url: &Url, headers: &HeaderMap) -> Result<Response, Error> {
+    Ok(self.answer(Method::GET, url, headers))
+  }
+
+  fn head(&self, url: &Url, headers: &HeaderMap) -> Result<Response, Error> {
+    Ok(self.answer(Method::HEAD, url, headers))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::config::{UrlPattern, UrlRule};
+  use super::*;
+
+  #[test]
+  fn test_memory_fetcher_does_not_follow_redirects() {
+    let fetcher = MemoryFetcher::new()
+      .redirect("https://example.com/old", 301, "/new")
+      .page("https://example.com/new", "hello");
+
+    let response = fetcher
+      .get(
+        &Url::parse("https://example.com/old").unwrap(),
+        &HeaderMap::new(),
+      )
+      .unwrap();
+
+    assert_eq!(response.status, StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(response.headers[header::LOCATION], "/new");
+    let missing = fetcher
+      .get(
+        &Url::parse("https://example.com/missing").unwrap(),
+        &HeaderMap::new(),
+      )
+      .unwrap();
+    assert_eq!(missing.status, StatusCode::NOT_FOUND);
+    assert_eq!(fetcher.requests().len(), 2);
+  }
+
+  #[test]
+  fn test_body_limit() {
+    let url = Url::parse("https://example.com/").unwrap();
+    let response = |headers| Response::new(url.clone(), StatusCode::OK, headers, "0123456789");
+
+    assert_eq!(response(HeaderMap::new()).text(10).unwrap(), "0123456789");
+    assert!(matches!(
+      response(HeaderMap::new()).bytes(9),
+      Err(Error::BodyTooLarge(9))
+    ));
+    // A declared length over the limit is refused without reading.
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("4096"));
+    assert!(matches!(
+      response(headers).bytes(1024),
+      Err(Error::BodyTooLarge(1024))
+    ));
+  }
+
+  #[test]
+  fn test_silent_server_times_out() {
+    // The connection is accepted by the kernel but never answered.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+    let limits = FetchLimits {
+      read_timeout: Duration::from_millis(200),
+      ..FetchLimits::default()
+    };
+
+    let result = HttpFetcher::new(&limits, &UrlRules::default())
+      .unwrap()
+      .get(&url, &HeaderMap::new());
+
+    assert!(matches!(result, Err(Error::ReadTimeout(timeout)) if timeout == limits.read_timeout));
+    drop(listener);
+  }
+
+  #[test]
+  fn test_rules_set_headers_and_timeout() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!(
+      "http://{}/staging/",
+      listener.local_addr().unwrap()
+    ))
+    .unwrap();
+    let mut rule = UrlRule::new(UrlPattern::parse("/staging/*").unwrap());
+    rule.timeout = Some(Duration::from_millis(300));
+    rule.headers.insert(
+      header::AUTHORIZATION,
+      HeaderValue::from_static("Bearer token"),
+    );
+    let server = std::thread::spawn(move || {
+      // Read the request and never answer it.
+      let (mut stream, _) = listener.accept().unwrap();
+      let mut request = Vec::new();
+      let mut buffer = [0; 1024];
+      while !request.ends_with(b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).unwrap();
+        request.extend_from_slice(&buffer[..read]);
+      }
+      std::thread::sleep(Duration::from_millis(500));
+      String::from_utf8(request).unwrap()
+    });
+
+    let fetcher = HttpFetcher::new(&FetchLimits::default(), &UrlRules(vec![rule])).unwrap();
+    let result = fetcher.get(&url, &HeaderMap::new());
+
+    assert!(
+      matches!(result, Err(Error::ReadTimeout(timeout)) if timeout == Duration::from_millis(300))
+    );
+    let request = server.join().unwrap().to_ascii_lowercase();
+    assert!(request.contains("authorization: bearer token\r\n"));
+  }
+}
@@ -0,0 +1,96 @@
+// Fragment validation
+// A link to `page.html#section-3` only works if the page has an element
+// with `id="section-3"` (or an `<a name="section-3">`). The anchors of every
+// parsed page are collected while its links are extracted, so checking a
+// fragment never costs a second download.
+use std::collections::HashSet;
+
+use scraper::{Html, Selector};
+
+/// Every fragment the document can be scrolled to.
+pub fn anchors(document: &Html) -> HashSet<String> {
+  let selector = Selector::parse("[id], a[name]").unwrap();
+  let mut anchors = HashSet::new();
+  for element in document.select(&selector) {
+    let element = element.value();
+    if let Some(id) = element.attr("id") {
+      anchors.insert(id.to_string());
+    }
+    // `name` only names an anchor on `<a>`, not on every element with an id.
+    if element.name() == "a" {
+      if let Some(name) = element.attr("name") {
+        anchors.insert(name.to_string());
+      }
+    }
+  }
+  anchors
+}
+
+/// Whether a fragment resolves in a document with the given anchors. An
+/// empty fragment and `#top` always scroll to the top of the page.
+pub fn has_anchor(anchors: &HashSet<String>, fragment: &str) -> bool {
+  if fragment.is_empty() || fragment.eq_ignore_ascii_case("top") {
+    return true;
+  }
+  anchors.contains(fragment) || anchors.contains(&percent_decode(fragment))
+}
+
+/// `Url` keeps fragments percent-encoded, while ids in the HTML are not.
+pub fn percent_decode(text: &str) -> String {
+  let bytes = text.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut idx = 0;
+  while idx < bytes.len() {
+    let hex = bytes
+      .get(idx + 1..idx + 3)
+      .and_then(|hex| std::str::from_utf8(hex).ok());
+    match (
+      bytes[idx],
+      hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+    ) {
+      (b'%', Some(byte)) => {
+        decoded.push(byte);
+        idx += 3;
+      }
+      (byte, _) => {
+        decoded.push(byte);
+        idx += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_ids_and_names_are_anchors() {
+    let document = Html::parse_document(
+      r#"<h2 id="install">Install</h2><a name="legacy"></a><div name="not-an-anchor"></div>"#,
+    );
+    let anchors = anchors(&document);
+    assert!(has_anchor(&anchors, "install"));
+    assert!(has_anchor(&anchors, "legacy"));
+    assert!(!has_anchor(&anchors, "not-an-anchor"));
+    assert!(has_anchor(&anchors, "top"));
+  }
+
+  #[test]
+  fn test_name_counts_only_on_links() {
+    let document =
+      Html::parse_document(r#"<div id="x" name="y"></div><input id="email" name="user-email">"#);
+    let anchors = anchors(&document);
+    assert!(has_anchor(&anchors, "x"));
+    assert!(has_anchor(&anchors, "email"));
+    assert!(!has_anchor(&anchors, "y"));
+    assert!(!has_anchor(&anchors, "user-email"));
+  }
+
+  #[test]
+  fn test_encoded_fragment_matches() {
+    let document = Html::parse_document(r#"<h2 id="café">Café</h2>"#);
+    assert!(has_anchor(&anchors(&document), "caf%C3%A9"));
+  }
+}
@@ -0,0 +1,352 @@
+// Local static sites
+// A built site in a directory is checked by pretending it is served at
+// `site_url`: URLs on that origin are answered from files under the root,
+// so relative and root-relative links resolve exactly as they will once
+// deployed. Anything else goes to the remote fetcher, if external links are
+// checked at all.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{StatusCode, Url};
+
+use super::config::CrawlConfig;
+use super::fetch::{Fetcher, HttpFetcher, Response};
+use super::fragments::percent_decode;
+use super::report::CrawlReport;
+use super::{check_links_with, Error};
+
+/// The page served for a directory URL.
+const INDEX_FILE: &str = "index.html";
+
+pub struct LocalSiteFetcher {
+  root: PathBuf,
+  site_url: Url,
+  remote: Arc<dyn Fetcher>,
+}
+
+impl LocalSiteFetcher {
+  pub fn new(root: &Path, site_url: Url, remote: Arc<dyn Fetcher>) -> LocalSiteFetcher {
+    LocalSiteFetcher {
+      root: root.to_path_buf(),
+      site_url,
+      remote,
+    }
+  }
+
+  fn is_local(&self, url: &Url) -> bool {
+    url.origin() == self.site_url.origin()
+  }
+
+  /// The file behind a local URL, or `None` if the path cannot name one.
+  fn file_path(&self, url: &Url) -> Option<PathBuf> {
+    let mut path = self.root.clone();
+    for segment in url.path().split('/').filter(|segment| !segment.is_empty()) {
+      let segment = percent_decode(segment);
+      // `Url` has resolved dot segments already, but not encoded ones.
+      if segment == "." || segment == ".." || segment.contains(['/', '\\']) {
+        return None;
+      }
+      path.push(segment);
+    }
+    Some(path)
+  }
+
+  fn serve(&self, url: &Url) -> Result<Response, Error> {
+    let Some(mut path) = self.file_path(url) else {
+      return Ok(response(
+        url,
+        StatusCode::NOT_FOUND,
+        HeaderMap::new(),
+        String::new(),
+      ));
+    };
+    if path.is_dir() {
+      // What a static file server does: `/guide` moves to `/guide/`, which
+      // serves the directory's index page.
+      if !url.path().ends_with('/') {
+        let mut location = url.clone();
+        location.set_path(&format!("{}/", url.path()));
+        let mut headers = HeaderMap::new();
+        headers.insert(
+          header::LOCATION,
+          HeaderValue::from_str(location.as_str()).unwrap(),
+        );
+        return Ok(response(
+          url,
+          StatusCode::MOVED_PERMANENTLY,
+          headers,
+          String::new(),
+        ));
+      }
+      path.push(INDEX_FILE);
+    }
+    if !path.is_file() {
+      return Ok(response(
+        url,
+        StatusCode::NOT_FOUND,
+        HeaderMap::new(),
+        String::new(),
+      ));
+    }
+
+    let content_type = content_type(&path);
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    // Only pages are ever parsed, so other files are not read at all.
+    let body = if matches!(content_type, "text/html" | "application/xhtml+xml") {
+      String::from_utf8_lossy(&fs::read(&path)?).into_owned()
+    } else {
+      String::new()
+    };
+    Ok(response(url, StatusCode::OK, headers, body))
+  }
+}
+
+impl Fetcher for LocalSiteFetcher {
+  fn get(&self, url: &Url, headers: &HeaderMap) -> Result<Response, Error> {
+    if self.is_local(url) {
+      self.serve(url)
+    } else {
+      self.remote.get(url, headers)
+    }
+  }
+
+  fn head(&self, url: &Url, headers: &HeaderMap) -> Result<Response, Error> {
+    if self.is_local(url) {
+      self.serve(url)
+    } else {
+      self.remote.head(url, headers)
+    }
+  }
+
+  /// Files on disk are never remote, so they skip robots.txt and rate
+  /// limits whatever the remote fetcher says.
+  fn is_remote(&self, url: &Url) -> bool {
+    !self.is_local(url) && self.remote.is_remote(url)
+  }
+}
+
+fn response(url: &Url, status: StatusCode, headers: HeaderMap, body: String) -> Response {
+  Response::new(url.clone(), status, headers, body)
+}
+
+fn content_type(path: &Path) -> &'static str {
+  let extension = path
+    .extension()
+    .and_then(|extension| extension.to_str())
+    .unwrap_or_default()
+    .to_ascii_lowercase();
+  match extension.as_str() {
+    "html" | "htm" => "text/html",
+    "xhtml" => "application/xhtml+xml",
+    "css" => "text/css",
+    "js" | "mjs" => "text/javascript",
+    "json" => "application/json",
+    "svg" => "image/svg+xml",
+    "png" => "image/png",
+    "jpg" | "jpeg" => "image/jpeg",
+    "gif" => "image/gif",
+    "webp" => "image/webp",
+    "pdf" => "application/pdf",
+    "txt" => "text/plain",
+    _ => "application/octet-stream",
+  }
+}
+
+/// Check the site built into `root` as if it were deployed at `site_url`.
+/// External links are fetched over HTTP unless `config.check_external` is
+/// off. Links to a directory's `index.html` count as links to the directory,
+/// which is the page served for it.
+pub fn check_local_site(
+  root: &Path,
+  site_url: Url,
+  config: CrawlConfig,
+) -> io::Result<CrawlReport> {
+  let remote = Arc::new(HttpFetcher::new(&config.limits, &config.rules).map_err(io::Error::other)?);
+  check_local_site_with(root, site_url, config, remote)
+}
+
+pub fn check_local_site_with(
+  root: &Path,
+  site_url: Url,
+  mut config: CrawlConfig,
+  remote: Arc<dyn Fetcher>,
+) -> io::Result<CrawlReport> {
+  if !root.is_dir() {
+    return Err(io::Error::new(
+      io::ErrorKind::NotFound,
+      format!("{} is not a directory", root.display()),
+    ));
+  }
+  config.normalize.directory_index = Some(String::from(INDEX_FILE));
+  let fetcher = LocalSiteFetcher::new(root, site_url.clone(), remote);
+  Ok(check_links_with(site_url, config, Arc::new(fetcher)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::config::SkipReason;
+  use super::super::fetch::MemoryFetcher;
+  use super::super::FailureKind;
+  use super::*;
+
+  /// A fresh directory under the system temp dir holding `files`.
+  fn site_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("link-checker-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for (path, contents) in files {
+      let path = root.join(path);
+      fs::create_dir_all(path.parent().unwrap()).unwrap();
+      fs::write(path, contents).unwrap();
+    }
+    root
+  }
+
+  fn site_url() -> Url {
+    Url::parse("https://docs.example.com/").unwrap()
+  }
+
+  #[test]
+  fn test_internal_links_are_checked_against_files() {
+    let root = site_dir(
+      "internal",
+      &[
+        (
+          "index.html",
+          r##"<a href="guide/">guide</a> <a href="/missing.html">missing</a>
+          <img src="img/logo.png"> <a href="guide/#setup">setup</a> <a href="#nowhere">x</a>"##,
+        ),
+        (
+          "guide/index.html",
+          r#"<h2 id="setup">Setup</h2> <a href="../index.html">home</a> <a href="/api">no slash</a>"#,
+        ),
+        ("api/index.html", ""),
+        ("img/logo.png", "not really a png"),
+      ],
+    );
+    let remote = Arc::new(MemoryFetcher::new());
+
+    let report =
+      check_local_site_with(&root, site_url(), CrawlConfig::default(), remote.clone()).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    let mut broken: Vec<_> = report
+      .failures()
+      .map(|(checked, error)| (checked.url.to_string(), error.kind()))
+      .collect();
+    broken.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+      broken,
+      vec![
+        (
+          String::from("https://docs.example.com/#nowhere"),
+          FailureKind::MissingFragment
+        ),
+        (
+          String::from("https://docs.example.com/missing.html"),
+          FailureKind::HttpStatus
+        ),
+      ]
+    );
+    // `/api` redirects to the directory, like a real server would.
+    let api = report
+      .checked_urls
+      .iter()
+      .find(|checked| checked.url.path() == "/api")
+      .unwrap();
+    assert_eq!(api.redirect_chain.len(), 1);
+    assert!(remote.requests().is_empty());
+  }
+
+  #[test]
+  fn test_local_pages_skip_robots_txt() {
+    // A robots.txt in the build is for the deployed site, not the check.
+    let root = site_dir(
+      "robots",
+      &[
+        ("robots.txt", "User-agent: *\nDisallow: /"),
+        ("index.html", r#"<a href="guide.html">guide</a>"#),
+        ("guide.html", ""),
+      ],
+    );
+    let remote = Arc::new(MemoryFetcher::new());
+    let fetcher = LocalSiteFetcher::new(&root, site_url(), remote.clone());
+
+    let report =
+      check_local_site_with(&root, site_url(), CrawlConfig::default(), remote.clone()).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert!(!fetcher.is_remote(&site_url().join("guide.html").unwrap()));
+    assert!(fetcher.is_remote(&Url::parse("https://example.org/").unwrap()));
+    assert!(report.failures().next().is_none());
+    assert_eq!(report.checked_urls.len(), 2);
+    assert!(!remote
+      .requests()
+      .iter()
+      .any(|url| url.path() == "/robots.txt"));
+  }
+
+  #[test]
+  fn test_xhtml_pages_are_parsed() {
+    let root = site_dir(
+      "xhtml",
+      &[
+        ("index.html", r#"<a href="book.xhtml">book</a>"#),
+        (
+          "book.xhtml",
+          r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><a href="missing.xhtml">next</a></body></html>"#,
+        ),
+      ],
+    );
+
+    let report = check_local_site_with(
+      &root,
+      site_url(),
+      CrawlConfig::default(),
+      Arc::new(MemoryFetcher::new()),
+    )
+    .unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    let broken: Vec<_> = report
+      .failures()
+      .map(|(checked, _)| checked.url.path().to_string())
+      .collect();
+    assert_eq!(broken, vec!["/missing.xhtml"]);
+  }
+
+  #[test]
+  fn test_external_links_are_optional() {
+    let root = site_dir(
+      "external",
+      &[(
+        "index.html",
+        r#"<a href="https://example.org/ok">ok</a> <a href="https://example.org/gone">gone</a>"#,
+      )],
+    );
+    let remote = || Arc::new(MemoryFetcher::new().page("https://example.org/ok", ""));
+
+    let checked =
+      check_local_site_with(&root, site_url(), CrawlConfig::default(), remote()).unwrap();
+    let offline = CrawlConfig {
+      check_external: false,
+      ..CrawlConfig::default()
+    };
+    let unchecked = check_local_site_with(&root, site_url(), offline, remote()).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(
+      checked.bad_urls(),
+      vec![Url::parse("https://example.org/gone").unwrap()]
+    );
+    assert!(unchecked.bad_urls().is_empty());
+    assert_eq!(unchecked.skipped_urls.len(), 2);
+    assert!(unchecked
+      .skipped_urls
+      .iter()
+      .all(|(_, reason)| *reason == SkipReason::External));
+  }
+}
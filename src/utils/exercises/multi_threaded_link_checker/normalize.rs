@@ -0,0 +1,178 @@
+// URL normalisation
+// Links to the same page are written many ways. `Url` already lowercases the
+// scheme and host and drops default ports; these rules take care of the
+// rest, so `CrawlState` can deduplicate on one canonical form per page.
+use reqwest::Url;
+
+#[derive(Debug, Clone)]
+pub struct NormalizeRules {
+  /// Treat `/docs/` and `/docs` as the same page. The root path is kept.
+  pub strip_trailing_slash: bool,
+  /// Treat `?a=1&b=2` and `?b=2&a=1` as the same query.
+  pub sort_query: bool,
+  /// Query parameters that never change the page, dropped before
+  /// comparing. A trailing `*` matches any suffix, as in `utm_*`.
+  pub ignored_params: Vec<String>,
+  /// The file a directory URL serves, as in `index.html`. `/docs/index.html`
+  /// is then the same page as `/docs/`.
+  pub directory_index: Option<String>,
+}
+
+impl Default for NormalizeRules {
+  fn default() -> Self {
+    let ignored_params = [
+      "utm_*", "fbclid", "gclid", "dclid", "msclkid", "yclid", "mc_cid", "mc_eid", "_hsenc",
+      "_hsmi",
+    ];
+    NormalizeRules {
+      strip_trailing_slash: true,
+      sort_query: true,
+      ignored_params: ignored_params
+        .iter()
+        .map(|param| param.to_string())
+        .collect(),
+      directory_index: None,
+    }
+  }
+}
+
+impl NormalizeRules {
+  /// Only what every URL gets: no fragment and uppercase percent-escapes.
+  pub fn minimal() -> NormalizeRules {
+    NormalizeRules {
+      strip_trailing_slash: false,
+      sort_query: false,
+      ignored_params: Vec::new(),
+      directory_index: None,
+    }
+  }
+
+  /// The canonical form of `url`. The fetched URL is never changed, this is
+  /// only what URLs are compared on.
+  pub fn normalize(&self, url: &Url) -> Url {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    if url.cannot_be_a_base() {
+      return url;
+    }
+
+    let mut path = uppercase_escapes(url.path());
+    if let Some(index) = &self.directory_index {
+      if path
+        .strip_suffix(index.as_str())
+        .is_some_and(|directory| directory.ends_with('/'))
+      {
+        path.truncate(path.len() - index.len());
+      }
+    }
+    if self.strip_trailing_slash && path.len() > 1 && path.ends_with('/') {
+      path.pop();
+    }
+    url.set_path(&path);
+
+    if let Some(query) = url.query() {
+      let mut params: Vec<String> = query
+        .split('&')
+        .filter(|param| !param.is_empty() && !self.is_ignored(param))
+        .map(uppercase_escapes)
+        .collect();
+      if self.sort_query {
+        params.sort();
+      }
+      let query = params.join("&");
+      url.set_query((!query.is_empty()).then_some(query.as_str()));
+    }
+    url
+  }
+
+  fn is_ignored(&self, param: &str) -> bool {
+    let name = param.split('=').next().unwrap_or_default();
+    self
+      .ignored_params
+      .iter()
+      .any(|ignored| match ignored.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == ignored,
+      })
+  }
+}
+
+/// `%2f` and `%2F` are the same byte.
+fn uppercase_escapes(text: &str) -> String {
+  let mut result = String::with_capacity(text.len());
+  let mut chars = text.chars();
+  while let Some(c) = chars.next() {
+    result.push(c);
+    if c == '%' {
+      for hex in chars.by_ref().take(2) {
+        result.push(hex.to_ascii_uppercase());
+      }
+    }
+  }
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn normalized(rules: &NormalizeRules, url: &str) -> String {
+    rules.normalize(&Url::parse(url).unwrap()).to_string()
+  }
+
+  #[test]
+  fn test_spellings_of_one_page_agree() {
+    let rules = NormalizeRules::default();
+    let spellings = [
+      "http://a.example/b",
+      "http://a.example/b/",
+      "HTTP://A.example:80/b#x",
+      "http://a.example/b?utm_source=news&utm_medium=mail",
+      "http://a.example/b?",
+    ];
+    for spelling in spellings {
+      assert_eq!(
+        normalized(&rules, spelling),
+        "http://a.example/b",
+        "{spelling}"
+      );
+    }
+    assert_eq!(
+      normalized(&rules, "https://a.example/?z=1&fbclid=x&a=%2f"),
+      "https://a.example/?a=%2F&z=1"
+    );
+  }
+
+  #[test]
+  fn test_directory_index_is_the_directory() {
+    let rules = NormalizeRules {
+      directory_index: Some(String::from("index.html")),
+      ..NormalizeRules::default()
+    };
+    assert_eq!(
+      normalized(&rules, "https://a.example/index.html#top"),
+      "https://a.example/"
+    );
+    assert_eq!(
+      normalized(&rules, "https://a.example/docs/index.html"),
+      normalized(&rules, "https://a.example/docs/")
+    );
+    assert_eq!(
+      normalized(&rules, "https://a.example/docs/old-index.html"),
+      "https://a.example/docs/old-index.html"
+    );
+  }
+
+  #[test]
+  fn test_rules_can_be_turned_off() {
+    let rules = NormalizeRules::minimal();
+    assert_eq!(
+      normalized(&rules, "http://a.example/b/?z=1&a=2#top"),
+      "http://a.example/b/?z=1&a=2"
+    );
+    assert_eq!(
+      normalized(&rules, "mailto:someone@example.com"),
+      "mailto:someone@example.com"
+    );
+  }
+}
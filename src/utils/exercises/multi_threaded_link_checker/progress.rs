@@ -0,0 +1,251 @@
+// Progress and cancellation
+// A running crawl reports what it is doing as a stream of events, so a
+// caller can show progress or collect statistics without waiting for the
+// report. The caller can also cancel it: no new requests are started, the
+// ones in progress are finished and recorded, and the crawl returns an
+// interrupted report (and a checkpoint, if configured).
+use std::fmt::Write;
+use std::io::{self, Write as _};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use reqwest::{StatusCode, Url};
+
+use super::config::SkipReason;
+use super::FailureKind;
+
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+  /// A URL was handed to the workers.
+  Queued { url: Url, depth: usize },
+  /// A worker started on a URL.
+  Started { url: Url },
+  /// A URL was checked, successfully or not.
+  Finished {
+    url: Url,
+    status: Option<StatusCode>,
+    elapsed: Duration,
+    error: Option<FailureKind>,
+  },
+  /// robots.txt did not allow checking a URL.
+  Disallowed { url: Url },
+  /// A URL was found but will not be checked.
+  Skipped { url: Url, reason: SkipReason },
+}
+
+/// Where a crawl sends its progress events. Events are dropped when nobody
+/// subscribed, or once the subscriber has gone away.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+  sender: Option<mpsc::Sender<ProgressEvent>>,
+}
+
+impl Progress {
+  /// A progress sink and the receiving end to subscribe with.
+  pub fn channel() -> (Progress, mpsc::Receiver<ProgressEvent>) {
+    let (sender, receiver) = mpsc::channel();
+    (
+      Progress {
+        sender: Some(sender),
+      },
+      receiver,
+    )
+  }
+
+  pub fn emit(&self, event: ProgressEvent) {
+    if let Some(sender) = &self.sender {
+      let _ = sender.send(event);
+    }
+  }
+}
+
+/// Set from any thread to cancel a crawl.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+  pub fn cancel(&self) {
+    self.0.store(true, Ordering::SeqCst);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.0.load(Ordering::SeqCst)
+  }
+}
+
+/// Cancel on Ctrl-C or SIGTERM. Only one handler can be installed per
+/// process.
+pub fn cancel_on_signal(token: &CancelToken) -> Result<(), ctrlc::Error> {
+  let token = token.clone();
+  ctrlc::set_handler(move || token.cancel())
+}
+
+/// Running totals over a crawl's events.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressStats {
+  pub queued: usize,
+  pub started: usize,
+  pub finished: usize,
+  pub disallowed: usize,
+  pub skipped: usize,
+  /// Failed URLs per kind, in the order the kinds were first seen.
+  pub errors: Vec<(FailureKind, usize)>,
+  /// Sum of the time spent on every finished URL.
+  pub total_latency: Duration,
+}
+
+impl ProgressStats {
+  pub fn record(&mut self, event: &ProgressEvent) {
+    match event {
+      ProgressEvent::Queued { .. } => self.queued += 1,
+      ProgressEvent::Started { .. } => self.started += 1,
+      ProgressEvent::Finished { elapsed, error, .. } => {
+        self.finished += 1;
+        self.total_latency += *elapsed;
+        if let Some(kind) = error {
+          match self.errors.iter_mut().find(|(seen, _)| seen == kind) {
+            Some((_, count)) => *count += 1,
+            None => self.errors.push((*kind, 1)),
+          }
+        }
+      }
+      ProgressEvent::Disallowed { .. } => self.disallowed += 1,
+      ProgressEvent::Skipped { .. } => self.skipped += 1,
+    }
+  }
+
+  /// URLs waiting for a worker.
+  pub fn queue_depth(&self) -> usize {
+    self.queued.saturating_sub(self.started)
+  }
+
+  /// URLs a worker is busy with.
+  pub fn in_progress(&self) -> usize {
+    self.started.saturating_sub(self.finished + self.disallowed)
+  }
+
+  pub fn error_count(&self) -> usize {
+    self.errors.iter().map(|(_, count)| count).sum()
+  }
+
+  pub fn mean_latency(&self) -> Option<Duration> {
+    let finished = u32::try_from(self.finished).ok().filter(|&n| n > 0)?;
+    Some(self.total_latency / finished)
+  }
+
+  /// One line describing the crawl `elapsed` after it started.
+  pub fn summary(&self, elapsed: Duration) -> String {
+    let rate = self.finished as f64 / elapsed.as_secs_f64().max(0.001);
+    let mut line = format!(
+      "{} checked ({rate:.1}/s), {} running, {} queued, {} skipped, {} errors",
+      self.finished,
+      self.in_progress(),
+      self.queue_depth(),
+      self.skipped + self.disallowed,
+      self.error_count(),
+    );
+    if !self.errors.is_empty() {
+      let kinds: Vec<String> = self
+        .errors
+        .iter()
+        .map(|(kind, count)| format!("{count} {kind}"))
+        .collect();
+      let _ = write!(line, " ({})", kinds.join(", "));
+    }
+    if let Some(latency) = self.mean_latency() {
+      let _ = write!(line, ", {} ms mean latency", latency.as_millis());
+    }
+    line
+  }
+}
+
+/// Keep a live summary of the crawl on one line of stderr until the crawl
+/// is over, then return the final totals.
+pub fn render_live(events: mpsc::Receiver<ProgressEvent>) -> ProgressStats {
+  const REFRESH: Duration = Duration::from_millis(200);
+  let started = Instant::now();
+  let mut stats = ProgressStats::default();
+  let mut last_render = Instant::now();
+  let render = |stats: &ProgressStats| {
+    let mut stderr = io::stderr().lock();
+    let _ = write!(stderr, "\r\x1b[2K{}", stats.summary(started.elapsed()));
+    let _ = stderr.flush();
+  };
+  loop {
+    match events.recv_timeout(REFRESH) {
+      Ok(event) => stats.record(&event),
+      Err(mpsc::RecvTimeoutError::Timeout) => {}
+      Err(mpsc::RecvTimeoutError::Disconnected) => break,
+    }
+    if last_render.elapsed() >= REFRESH {
+      render(&stats);
+      last_render = Instant::now();
+    }
+  }
+  render(&stats);
+  eprintln!();
+  stats
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn url(path: &str) -> Url {
+    Url::parse("https://example.com/")
+      .unwrap()
+      .join(path)
+      .unwrap()
+  }
+
+  #[test]
+  fn test_stats_follow_the_events() {
+    let mut stats = ProgressStats::default();
+    let events = [
+      ProgressEvent::Queued {
+        url: url("/"),
+        depth: 0,
+      },
+      ProgressEvent::Queued {
+        url: url("/a"),
+        depth: 1,
+      },
+      ProgressEvent::Queued {
+        url: url("/b"),
+        depth: 1,
+      },
+      ProgressEvent::Started { url: url("/") },
+      ProgressEvent::Finished {
+        url: url("/"),
+        status: Some(StatusCode::OK),
+        elapsed: Duration::from_millis(30),
+        error: None,
+      },
+      ProgressEvent::Started { url: url("/a") },
+      ProgressEvent::Finished {
+        url: url("/a"),
+        status: Some(StatusCode::NOT_FOUND),
+        elapsed: Duration::from_millis(10),
+        error: Some(FailureKind::HttpStatus),
+      },
+      ProgressEvent::Started { url: url("/b") },
+      ProgressEvent::Skipped {
+        url: url("/c"),
+        reason: SkipReason::MaxPages,
+      },
+    ];
+    for event in &events {
+      stats.record(event);
+    }
+
+    assert_eq!(stats.queue_depth(), 0);
+    assert_eq!(stats.in_progress(), 1);
+    assert_eq!(stats.error_count(), 1);
+    assert_eq!(stats.mean_latency(), Some(Duration::from_millis(20)));
+    assert_eq!(
+      stats.summary(Duration::from_secs(1)),
+      "2 checked (2.0/s), 1 running, 0 queued, 1 skipped, 1 errors (1 HTTP status), 20 ms mean latency"
+    );
+  }
+}
@@ -0,0 +1,126 @@
+// Per-host rate limiting
+// Every host gets a token bucket: `burst` requests may go out at once, after
+// which requests are spaced `1 / requests_per_second` apart. Workers reserve
+// a token under the lock and sleep outside it, so a busy host never blocks
+// requests to other hosts.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::Url;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+  pub requests_per_second: f64,
+  pub burst: u32,
+}
+
+impl Default for RateLimit {
+  fn default() -> Self {
+    RateLimit {
+      requests_per_second: 10.0,
+      burst: 10,
+    }
+  }
+}
+
+#[derive(Debug)]
+struct Bucket {
+  /// Negative once more requests are queued than there are tokens.
+  tokens: f64,
+  updated: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct HostRateLimiter {
+  limit: Option<RateLimit>,
+  buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl HostRateLimiter {
+  /// A limiter enforcing `limit` on every host, or nothing if it is `None`.
+  pub fn new(limit: Option<RateLimit>) -> HostRateLimiter {
+    HostRateLimiter {
+      limit,
+      buckets: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Take a token from the URL's host and return how long to wait before
+  /// sending the request.
+  pub fn reserve(&self, url: &Url) -> Duration {
+    self.reserve_at(url, Instant::now())
+  }
+
+  /// Like `reserve`, but sleeps the blocking thread for the wait itself.
+  pub fn acquire(&self, url: &Url) {
+    let wait = self.reserve(url);
+    if !wait.is_zero() {
+      std::thread::sleep(wait);
+    }
+  }
+
+  fn reserve_at(&self, url: &Url, now: Instant) -> Duration {
+    let Some(limit) = self.limit else {
+      return Duration::ZERO;
+    };
+    let host = url.host_str().unwrap_or_default().to_string();
+    let burst = f64::from(limit.burst.max(1));
+    let mut buckets = self.buckets.lock().unwrap();
+    let bucket = buckets.entry(host).or_insert(Bucket {
+      tokens: burst,
+      updated: now,
+    });
+    let refill = now.saturating_duration_since(bucket.updated).as_secs_f64();
+    bucket.tokens = (bucket.tokens + refill * limit.requests_per_second).min(burst);
+    bucket.updated = now;
+    bucket.tokens -= 1.0;
+    if bucket.tokens >= 0.0 {
+      Duration::ZERO
+    } else {
+      Duration::from_secs_f64(-bucket.tokens / limit.requests_per_second)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn url(url: &str) -> Url {
+    Url::parse(url).unwrap()
+  }
+
+  #[test]
+  fn test_requests_beyond_the_burst_are_spaced_out() {
+    let limiter = HostRateLimiter::new(Some(RateLimit {
+      requests_per_second: 2.0,
+      burst: 2,
+    }));
+    let now = Instant::now();
+    let page = url("https://example.com/");
+    assert_eq!(limiter.reserve_at(&page, now), Duration::ZERO);
+    assert_eq!(limiter.reserve_at(&page, now), Duration::ZERO);
+    assert_eq!(limiter.reserve_at(&page, now), Duration::from_millis(500));
+    assert_eq!(limiter.reserve_at(&page, now), Duration::from_millis(1000));
+    // Other hosts have their own bucket.
+    assert_eq!(
+      limiter.reserve_at(&url("https://other.example/"), now),
+      Duration::ZERO
+    );
+    // Two seconds later the queue has drained and one token is back.
+    let later = now + Duration::from_secs(2);
+    assert_eq!(limiter.reserve_at(&page, later), Duration::ZERO);
+  }
+
+  #[test]
+  fn test_no_limit_never_waits() {
+    let limiter = HostRateLimiter::new(None);
+    for _ in 0..100 {
+      assert_eq!(
+        limiter.reserve(&url("https://example.com/")),
+        Duration::ZERO
+      );
+    }
+  }
+}
@@ -0,0 +1,220 @@
+// Redirects
+// Fetchers never follow redirects on their own; the crawler follows them
+// one hop at a time so every hop ends up in the report. A link answered by
+// a permanent redirect still works but should be updated, while loops and
+// redirects from https to http are failures.
+use std::collections::HashSet;
+
+use reqwest::header::{self, HeaderMap};
+use reqwest::{StatusCode, Url};
+
+use super::fetch::{Fetcher, Response};
+use super::Error;
+
+#[derive(Debug, Clone)]
+pub struct RedirectPolicy {
+  /// Hops followed before giving up with `TooManyRedirects`.
+  pub max_hops: usize,
+  /// Whether an https URL may redirect to plain http.
+  pub allow_downgrade: bool,
+}
+
+impl Default for RedirectPolicy {
+  fn default() -> Self {
+    RedirectPolicy {
+      max_hops: 10,
+      allow_downgrade: false,
+    }
+  }
+}
+
+/// One hop of a redirect chain: the status that was answered and the URL
+/// it pointed to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+  pub status: StatusCode,
+  pub url: Url,
+}
+
+impl Redirect {
+  /// 301 and 308 tell clients to use the new URL from now on.
+  pub fn is_permanent(&self) -> bool {
+    matches!(
+      self.status,
+      StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT
+    )
+  }
+}
+
+/// The hops taken so far from one starting URL.
+#[derive(Debug)]
+pub struct RedirectChain<'a> {
+  policy: &'a RedirectPolicy,
+  seen: HashSet<Url>,
+  pub hops: Vec<Redirect>,
+}
+
+impl<'a> RedirectChain<'a> {
+  pub fn new(policy: &'a RedirectPolicy, start_url: &Url) -> RedirectChain<'a> {
+    RedirectChain {
+      policy,
+      seen: HashSet::from([start_url.clone()]),
+      hops: Vec::new(),
+    }
+  }
+
+  /// Look at the response `url` answered with. Returns the URL to fetch
+  /// next if it was a redirect to follow, or `None` if it is the final one.
+  pub fn next(
+    &mut self,
+    url: &Url,
+    status: StatusCode,
+    headers: &HeaderMap,
+  ) -> Result<Option<Url>, Error> {
+    if !status.is_redirection() {
+      return Ok(None);
+    }
+    let location = headers
+      .get(header::LOCATION)
+      .and_then(|location| location.to_str().ok())
+      .and_then(|location| url.join(location).ok());
+    // A 304, or a redirect without anywhere to go, is answered as is.
+    let Some(mut location) = location else {
+      return Ok(None);
+    };
+    // The fragment of the original link is what matters, not the hop's.
+    location.set_fragment(None);
+    if url.scheme() == "https" && location.scheme() == "http" && !self.policy.allow_downgrade {
+      return Err(Error::InsecureRedirect(location));
+    }
+    if !self.seen.insert(location.clone()) {
+      return Err(Error::RedirectLoop(location));
+    }
+    if self.hops.len() >= self.policy.max_hops {
+      return Err(Error::TooManyRedirects(location));
+    }
+    self.hops.push(Redirect {
+      status,
+      url: location.clone(),
+    });
+    Ok(Some(location))
+  }
+}
+
+/// GET `url` through `fetcher`, following redirects as `policy` allows.
+/// Returns the final response and the hops that led to it.
+pub fn follow(
+  fetcher: &dyn Fetcher,
+  url: &Url,
+  policy: &RedirectPolicy,
+) -> Result<(Response, Vec<Redirect>), Error> {
+  follow_with_headers(fetcher, url, &HeaderMap::new(), policy)
+}
+
+/// Like `follow`, sending `headers` with every hop.
+pub fn follow_with_headers(
+  fetcher: &dyn Fetcher,
+  url: &Url,
+  headers: &HeaderMap,
+  policy: &RedirectPolicy,
+) -> Result<(Response, Vec<Redirect>), Error> {
+  follow_by(url, policy, |url| fetcher.get(url, headers))
+}
+
+/// Like `follow_with_headers`, with HEAD requests.
+pub fn follow_head(
+  fetcher: &dyn Fetcher,
+  url: &Url,
+  headers: &HeaderMap,
+  policy: &RedirectPolicy,
+) -> Result<(Response, Vec<Redirect>), Error> {
+  follow_by(url, policy, |url| fetcher.head(url, headers))
+}
+
+fn follow_by(
+  url: &Url,
+  policy: &RedirectPolicy,
+  send: impl Fn(&Url) -> Result<Response, Error>,
+) -> Result<(Response, Vec<Redirect>), Error> {
+  let mut chain = RedirectChain::new(policy, url);
+  let mut response = send(url)?;
+  while let Some(next_url) = chain.next(&response.url, response.status, &response.headers)? {
+    response = send(&next_url)?;
+  }
+  Ok((response, chain.hops))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::fetch::MemoryFetcher;
+  use super::*;
+
+  fn url(url: &str) -> Url {
+    Url::parse(url).unwrap()
+  }
+
+  #[test]
+  fn test_every_hop_is_recorded() {
+    let fetcher = MemoryFetcher::new()
+      .redirect("http://example.com/old", 301, "https://example.com/old")
+      .redirect("https://example.com/old", 302, "/new")
+      .page("https://example.com/new", "");
+
+    let (response, hops) = follow(
+      &fetcher,
+      &url("http://example.com/old"),
+      &RedirectPolicy::default(),
+    )
+    .unwrap();
+
+    assert_eq!(response.url, url("https://example.com/new"));
+    assert_eq!(
+      hops,
+      vec![
+        Redirect {
+          status: StatusCode::MOVED_PERMANENTLY,
+          url: url("https://example.com/old")
+        },
+        Redirect {
+          status: StatusCode::FOUND,
+          url: url("https://example.com/new")
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn test_loops_and_downgrades_fail() {
+    let fetcher = MemoryFetcher::new()
+      .redirect("https://example.com/a", 302, "/b")
+      .redirect("https://example.com/b", 302, "/a")
+      .redirect("https://example.com/insecure", 301, "http://example.com/");
+    let policy = RedirectPolicy::default();
+
+    let looped = follow(&fetcher, &url("https://example.com/a"), &policy);
+    assert!(matches!(looped, Err(Error::RedirectLoop(url)) if url.path() == "/a"));
+    let downgraded = follow(&fetcher, &url("https://example.com/insecure"), &policy);
+    assert!(matches!(downgraded, Err(Error::InsecureRedirect(_))));
+    let allowed = RedirectPolicy {
+      allow_downgrade: true,
+      ..RedirectPolicy::default()
+    };
+    assert!(follow(&fetcher, &url("https://example.com/insecure"), &allowed).is_ok());
+  }
+
+  #[test]
+  fn test_hop_limit() {
+    let fetcher = MemoryFetcher::new()
+      .redirect("https://example.com/1", 307, "/2")
+      .redirect("https://example.com/2", 307, "/3")
+      .redirect("https://example.com/3", 307, "/4");
+    let policy = RedirectPolicy {
+      max_hops: 2,
+      ..RedirectPolicy::default()
+    };
+
+    let result = follow(&fetcher, &url("https://example.com/1"), &policy);
+
+    assert!(matches!(result, Err(Error::TooManyRedirects(url)) if url.path() == "/4"));
+  }
+}
@@ -0,0 +1,562 @@
+// Crawl reports
+// Everything the crawl learnt about each URL, plus serialisers for tools
+// that read it: plain text for people, JSON for scripts, JUnit XML so CI
+// shows every broken link as a failed test case, and SARIF for
+// code-scanning dashboards.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use reqwest::{StatusCode, Url};
+use serde::Serialize;
+use serde_json::json;
+
+use super::config::SkipReason;
+use super::extract::ResourceKind;
+use super::redirect::Redirect;
+use super::{Error, FailureKind, LinkSource, USER_AGENT};
+
+/// The outcome of one fetched URL. Kept for every URL, not only failures.
+#[derive(Debug)]
+pub struct CheckedUrl {
+  pub url: Url,
+  pub status: Option<StatusCode>,
+  pub redirect_chain: Vec<Redirect>,
+  pub elapsed: Duration,
+  /// Every link pointing at this URL, empty for the start URL.
+  pub sources: Vec<LinkSource>,
+  pub error: Option<Error>,
+}
+
+impl CheckedUrl {
+  /// Where links to this URL should point instead, if it answered with a
+  /// permanent redirect: the URL at the end of the permanent hops.
+  pub fn permanent_redirect(&self) -> Option<&Url> {
+    self
+      .redirect_chain
+      .iter()
+      .take_while(|redirect| redirect.is_permanent())
+      .last()
+      .map(|redirect| &redirect.url)
+  }
+}
+
+/// One broken link as seen from the page that contains it.
+#[derive(Debug)]
+pub struct BrokenLink<'a> {
+  pub url: &'a Url,
+  pub error: &'a Error,
+  pub source: Option<&'a LinkSource>,
+}
+
+#[derive(Debug, Default)]
+pub struct CrawlReport {
+  pub checked_urls: Vec<CheckedUrl>,
+  /// Pages robots.txt asked us not to fetch. These are not broken.
+  pub disallowed_urls: Vec<Url>,
+  /// URLs that were found but held back by the crawl config.
+  pub skipped_urls: Vec<(Url, SkipReason)>,
+  /// Pages listed in a sitemap that no checked page links to.
+  pub orphan_urls: Vec<Url>,
+  /// Links with a scheme other than http(s), like `mailto:` or
+  /// `javascript:`. These are never followed.
+  pub non_http_urls: Vec<Url>,
+  /// Whether the crawl was stopped before it finished. Missing fragments,
+  /// skipped, orphan and non-http URLs are only known for complete crawls.
+  pub interrupted: bool,
+}
+
+impl CrawlReport {
+  /// Working links that should be updated to where they redirect.
+  pub fn permanent_redirects(&self) -> impl Iterator<Item = (&CheckedUrl, &Url)> {
+    self
+      .checked_urls
+      .iter()
+      .filter(|checked| checked.error.is_none())
+      .filter_map(|checked| checked.permanent_redirect().map(|target| (checked, target)))
+  }
+
+  pub fn failures(&self) -> impl Iterator<Item = (&CheckedUrl, &Error)> {
+    self
+      .checked_urls
+      .iter()
+      .filter_map(|checked| checked.error.as_ref().map(|error| (checked, error)))
+  }
+
+  pub fn bad_urls(&self) -> Vec<Url> {
+    self
+      .failures()
+      .map(|(checked, _)| checked.url.clone())
+      .collect()
+  }
+
+  /// Group the broken links by the page they are on. A failing start URL
+  /// has no referring page and is listed under `None`.
+  pub fn failures_by_page(&self) -> BTreeMap<Option<&Url>, Vec<BrokenLink<'_>>> {
+    let mut by_page: BTreeMap<Option<&Url>, Vec<BrokenLink<'_>>> = BTreeMap::new();
+    for (checked, error) in self.failures() {
+      let url = &checked.url;
+      if checked.sources.is_empty() {
+        let broken_link = BrokenLink {
+          url,
+          error,
+          source: None,
+        };
+        by_page.entry(None).or_default().push(broken_link);
+      }
+      for source in &checked.sources {
+        let broken_link = BrokenLink {
+          url,
+          error,
+          source: Some(source),
+        };
+        by_page
+          .entry(Some(&source.page))
+          .or_default()
+          .push(broken_link);
+      }
+    }
+    by_page
+  }
+
+  fn total_elapsed(&self) -> Duration {
+    self
+      .checked_urls
+      .iter()
+      .map(|checked| checked.elapsed)
+      .sum()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+  Text,
+  Json,
+  JUnit,
+  Sarif,
+}
+
+impl OutputFormat {
+  pub fn render(self, report: &CrawlReport) -> String {
+    match self {
+      OutputFormat::Text => to_text(report),
+      OutputFormat::Json => to_json(report),
+      OutputFormat::JUnit => to_junit_xml(report),
+      OutputFormat::Sarif => to_sarif(report),
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+  summary: JsonSummary,
+  checked_urls: Vec<JsonCheckedUrl<'a>>,
+  disallowed_urls: Vec<&'a str>,
+  skipped_urls: Vec<JsonSkippedUrl<'a>>,
+  orphan_urls: Vec<&'a str>,
+  non_http_urls: Vec<&'a str>,
+}
+
+#[derive(Serialize)]
+struct JsonSummary {
+  checked: usize,
+  broken: usize,
+  permanent_redirects: usize,
+  disallowed: usize,
+  skipped: usize,
+  orphans: usize,
+  non_http: usize,
+}
+
+#[derive(Serialize)]
+struct JsonCheckedUrl<'a> {
+  url: &'a str,
+  status: Option<u16>,
+  redirect_chain: Vec<JsonRedirect<'a>>,
+  /// Where the link should point instead, for permanent redirects.
+  update_to: Option<&'a str>,
+  elapsed_ms: f64,
+  referrers: Vec<JsonReferrer<'a>>,
+  error: Option<JsonError>,
+}
+
+#[derive(Serialize)]
+struct JsonRedirect<'a> {
+  status: u16,
+  url: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonReferrer<'a> {
+  page: &'a str,
+  kind: &'static str,
+  element: &'a str,
+  text: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonSkippedUrl<'a> {
+  url: &'a str,
+  reason: String,
+}
+
+#[derive(Serialize)]
+struct JsonError {
+  kind: &'static str,
+  message: String,
+}
+
+/// Broken links grouped by the page they are on, then everything else
+/// worth a look, one line each.
+pub fn to_text(report: &CrawlReport) -> String {
+  let mut text = String::new();
+  for (page, broken_links) in report.failures_by_page() {
+    match page {
+      Some(page) => writeln!(text, "Broken links on {page:#}:"),
+      None => writeln!(text, "Broken start URL or sitemap:"),
+    }
+    .unwrap();
+    for broken_link in broken_links {
+      let (kind, element, text_content) = match broken_link.source {
+        Some(source) => (source.kind, source.element.as_str(), source.text.as_str()),
+        None => (ResourceKind::Hyperlink, "-", ""),
+      };
+      writeln!(
+        text,
+        "  broken {kind} {:#} <{element}> {text_content:?} [{}]: {}",
+        broken_link.url,
+        broken_link.error.kind(),
+        broken_link.error
+      )
+      .unwrap();
+    }
+  }
+  for (checked, target) in report.permanent_redirects() {
+    for source in &checked.sources {
+      writeln!(
+        text,
+        "Update {:#} on {:#}: moved permanently to {target:#}",
+        checked.url, source.page
+      )
+      .unwrap();
+    }
+  }
+  writeln!(text, "Bad URLs: {:#?}", report.bad_urls()).unwrap();
+  writeln!(
+    text,
+    "Disallowed by robots.txt: {:#?}",
+    report.disallowed_urls
+  )
+  .unwrap();
+  for (url, reason) in &report.skipped_urls {
+    writeln!(text, "Skipped {url:#}: {reason}").unwrap();
+  }
+  for url in &report.non_http_urls {
+    writeln!(text, "Not checked, not an http(s) link: {url}").unwrap();
+  }
+  for url in &report.orphan_urls {
+    writeln!(text, "Orphan page {url:#}: only listed in a sitemap").unwrap();
+  }
+  text
+}
+
+pub fn to_json(report: &CrawlReport) -> String {
+  let checked_urls = report
+    .checked_urls
+    .iter()
+    .map(|checked| JsonCheckedUrl {
+      url: checked.url.as_str(),
+      status: checked.status.map(|status| status.as_u16()),
+      redirect_chain: checked
+        .redirect_chain
+        .iter()
+        .map(|redirect| JsonRedirect {
+          status: redirect.status.as_u16(),
+          url: redirect.url.as_str(),
+        })
+        .collect(),
+      update_to: checked.permanent_redirect().map(Url::as_str),
+      elapsed_ms: checked.elapsed.as_secs_f64() * 1000.0,
+      referrers: checked
+        .sources
+        .iter()
+        .map(|source| JsonReferrer {
+          page: source.page.as_str(),
+          kind: source.kind.id(),
+          element: &source.element,
+          text: &source.text,
+        })
+        .collect(),
+      error: checked.error.as_ref().map(|error| JsonError {
+        kind: error.kind().id(),
+        message: error.to_string(),
+      }),
+    })
+    .collect();
+  let json_report = JsonReport {
+    summary: JsonSummary {
+      checked: report.checked_urls.len(),
+      broken: report.failures().count(),
+      permanent_redirects: report.permanent_redirects().count(),
+      disallowed: report.disallowed_urls.len(),
+      skipped: report.skipped_urls.len(),
+      orphans: report.orphan_urls.len(),
+      non_http: report.non_http_urls.len(),
+    },
+    checked_urls,
+    disallowed_urls: report.disallowed_urls.iter().map(Url::as_str).collect(),
+    skipped_urls: report
+      .skipped_urls
+      .iter()
+      .map(|(url, reason)| JsonSkippedUrl {
+        url: url.as_str(),
+        reason: reason.to_string(),
+      })
+      .collect(),
+    orphan_urls: report.orphan_urls.iter().map(Url::as_str).collect(),
+    non_http_urls: report.non_http_urls.iter().map(Url::as_str).collect(),
+  };
+  serde_json::to_string_pretty(&json_report).unwrap()
+}
+
+/// One `<testcase>` per checked URL, named after the URL and classed by the
+/// first page linking to it. robots.txt exclusions show up as skipped.
+pub fn to_junit_xml(report: &CrawlReport) -> String {
+  let tests = report.checked_urls.len() + report.disallowed_urls.len();
+  let failures = report.failures().count();
+  let time = report.total_elapsed().as_secs_f64();
+
+  let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+  xml.push_str(&format!(
+    "<testsuites name=\"link-checker\" tests=\"{tests}\" failures=\"{failures}\" time=\"{time:.3}\">\n"
+  ));
+  xml.push_str(&format!(
+    "  <testsuite name=\"link-checker\" tests=\"{tests}\" failures=\"{failures}\" skipped=\"{}\" time=\"{time:.3}\">\n",
+    report.disallowed_urls.len()
+  ));
+  for checked in &report.checked_urls {
+    let classname = checked
+      .sources
+      .first()
+      .map_or("start", |source| source.page.as_str());
+    xml.push_str(&format!(
+      "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+      escape_xml(classname),
+      escape_xml(checked.url.as_str()),
+      checked.elapsed.as_secs_f64()
+    ));
+    let Some(error) = &checked.error else {
+      xml.push_str("/>\n");
+      continue;
+    };
+    xml.push_str(">\n");
+    let mut details = String::new();
+    for source in &checked.sources {
+      details.push_str(&format!(
+        "{} on {} in <{}> {:?}\n",
+        source.kind, source.page, source.element, source.text
+      ));
+    }
+    xml.push_str(&format!(
+      "      <failure type=\"{}\" message=\"{}\">{}</failure>\n",
+      error.kind().id(),
+      escape_xml(&error.to_string()),
+      escape_xml(&details)
+    ));
+    xml.push_str("    </testcase>\n");
+  }
+  for url in &report.disallowed_urls {
+    xml.push_str(&format!(
+      "    <testcase classname=\"robots.txt\" name=\"{}\">\n      <skipped message=\"disallowed by robots.txt\"/>\n    </testcase>\n",
+      escape_xml(url.as_str())
+    ));
+  }
+  xml.push_str("  </testsuite>\n</testsuites>\n");
+  xml
+}
+
+fn escape_xml(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&apos;"),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+/// SARIF 2.1.0 with one rule per failure kind and one result per broken
+/// link, located on the page that contains it.
+pub fn to_sarif(report: &CrawlReport) -> String {
+  let kinds = [
+    FailureKind::HttpStatus,
+    FailureKind::Connect,
+    FailureKind::Timeout,
+    FailureKind::BodyTooLarge,
+    FailureKind::MissingFragment,
+    FailureKind::Redirect,
+    FailureKind::Other,
+  ];
+  let mut rules: Vec<_> = kinds
+    .iter()
+    .map(|kind| {
+      json!({
+        "id": format!("broken-link/{}", kind.id()),
+        "shortDescription": { "text": format!("Broken link: {kind}") },
+      })
+    })
+    .collect();
+  rules.push(json!({
+    "id": "permanent-redirect",
+    "shortDescription": { "text": "Link target moved permanently" },
+  }));
+
+  let mut results: Vec<_> = report
+    .failures_by_page()
+    .into_iter()
+    .flat_map(|(page, broken_links)| {
+      broken_links.into_iter().map(move |broken_link| {
+        let location = page.unwrap_or(broken_link.url);
+        let text = match broken_link.source {
+          Some(source) => format!(
+            "Broken {} {} ({:?}): {}",
+            source.kind, broken_link.url, source.text, broken_link.error
+          ),
+          None => format!("{} is broken: {}", broken_link.url, broken_link.error),
+        };
+        json!({
+          "ruleId": format!("broken-link/{}", broken_link.error.kind().id()),
+          "level": "error",
+          "message": { "text": text },
+          "locations": [{
+            "physicalLocation": { "artifactLocation": { "uri": location.as_str() } }
+          }],
+        })
+      })
+    })
+    .collect();
+  // Permanent redirects still work, so they are warnings on every page
+  // linking to the old URL.
+  for (checked, target) in report.permanent_redirects() {
+    for source in &checked.sources {
+      results.push(json!({
+        "ruleId": "permanent-redirect",
+        "level": "warning",
+        "message": { "text": format!("{} {} moved permanently to {}", source.kind, checked.url, target) },
+        "locations": [{
+          "physicalLocation": { "artifactLocation": { "uri": source.page.as_str() } }
+        }],
+      }));
+    }
+  }
+
+  let sarif = json!({
+    "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+    "version": "2.1.0",
+    "runs": [{
+      "tool": { "driver": { "name": USER_AGENT, "rules": rules } },
+      "results": results,
+    }],
+  });
+  serde_json::to_string_pretty(&sarif).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sample_report() -> CrawlReport {
+    let home = Url::parse("https://example.com/").unwrap();
+    let source = LinkSource {
+      page: home.clone(),
+      kind: ResourceKind::Stylesheet,
+      element: String::from("link"),
+      text: String::from("Tom & Jerry"),
+    };
+    CrawlReport {
+      checked_urls: vec![
+        CheckedUrl {
+          url: home,
+          status: Some(StatusCode::OK),
+          redirect_chain: Vec::new(),
+          elapsed: Duration::from_millis(20),
+          sources: Vec::new(),
+          error: None,
+        },
+        CheckedUrl {
+          url: Url::parse("https://example.com/missing").unwrap(),
+          status: Some(StatusCode::NOT_FOUND),
+          redirect_chain: Vec::new(),
+          elapsed: Duration::from_millis(5),
+          sources: vec![source],
+          error: Some(Error::BadResponse {
+            status: StatusCode::NOT_FOUND,
+            retry_after: None,
+          }),
+        },
+      ],
+      disallowed_urls: vec![Url::parse("https://example.com/private").unwrap()],
+      skipped_urls: Vec::new(),
+      orphan_urls: vec![Url::parse("https://example.com/unlinked").unwrap()],
+      non_http_urls: vec![Url::parse("mailto:someone@example.com").unwrap()],
+      interrupted: false,
+    }
+  }
+
+  #[test]
+  fn test_json_lists_every_checked_url() {
+    let json: serde_json::Value = serde_json::from_str(&to_json(&sample_report())).unwrap();
+    assert_eq!(json["summary"]["checked"], 2);
+    assert_eq!(json["summary"]["broken"], 1);
+    assert_eq!(json["summary"]["orphans"], 1);
+    assert_eq!(json["non_http_urls"][0], "mailto:someone@example.com");
+    assert_eq!(json["checked_urls"][0]["status"], 200);
+    assert_eq!(json["checked_urls"][1]["error"]["kind"], "http_status");
+    assert_eq!(
+      json["checked_urls"][1]["referrers"][0]["page"],
+      "https://example.com/"
+    );
+    assert_eq!(
+      json["checked_urls"][1]["referrers"][0]["kind"],
+      "stylesheet"
+    );
+  }
+
+  #[test]
+  fn test_text_groups_broken_links_by_page() {
+    let text = to_text(&sample_report());
+    assert!(text.starts_with("Broken links on https://example.com/:\n"));
+    assert!(text.contains(
+      "  broken stylesheet https://example.com/missing <link> \"Tom & Jerry\" [HTTP status]"
+    ));
+    assert!(text.contains("Orphan page https://example.com/unlinked: only listed in a sitemap"));
+  }
+
+  #[test]
+  fn test_junit_marks_broken_links_as_failures() {
+    let xml = to_junit_xml(&sample_report());
+    assert!(xml.contains("tests=\"3\" failures=\"1\""));
+    assert!(
+      xml.contains("<failure type=\"http_status\" message=\"bad http response: 404 Not Found\">")
+    );
+    assert!(xml.contains("Tom &amp; Jerry"));
+    assert!(xml.contains("<skipped message=\"disallowed by robots.txt\"/>"));
+  }
+
+  #[test]
+  fn test_sarif_locates_results_on_referring_page() {
+    let sarif: serde_json::Value = serde_json::from_str(&to_sarif(&sample_report())).unwrap();
+    let results = &sarif["runs"][0]["results"];
+    assert_eq!(results.as_array().unwrap().len(), 1);
+    assert_eq!(results[0]["ruleId"], "broken-link/http_status");
+    assert_eq!(
+      results[0]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+      "https://example.com/"
+    );
+  }
+}
@@ -0,0 +1,120 @@
+// Retries
+// A 503 or a connection reset says little about the link itself, so such
+// failures are retried a few times before a URL is reported as broken. The
+// delay doubles on every attempt and is jittered so that workers which
+// failed together do not all come back at the same moment.
+use std::time::{Duration, SystemTime};
+
+use rand::Rng;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+  /// Attempts after the first one. `0` disables retries.
+  pub max_retries: u32,
+  /// Delay before the first retry, doubled for every retry after it.
+  pub initial_backoff: Duration,
+  /// Longest delay between two attempts. A `Retry-After` asking for more
+  /// than this is not waited for and the URL fails straight away.
+  pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    RetryPolicy {
+      max_retries: 2,
+      initial_backoff: Duration::from_millis(250),
+      max_backoff: Duration::from_secs(30),
+    }
+  }
+}
+
+impl RetryPolicy {
+  /// A policy that never retries.
+  pub fn none() -> RetryPolicy {
+    RetryPolicy {
+      max_retries: 0,
+      ..RetryPolicy::default()
+    }
+  }
+
+  /// How long to wait before retrying after `attempt` failed attempts, or
+  /// `None` to give up. `retry_after` is what the server asked for, if
+  /// anything, and replaces the computed backoff.
+  pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+    if attempt > self.max_retries {
+      return None;
+    }
+    if let Some(retry_after) = retry_after {
+      return (retry_after <= self.max_backoff).then_some(retry_after);
+    }
+    let backoff = self
+      .initial_backoff
+      .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+      .min(self.max_backoff);
+    // Anywhere between half and the full backoff.
+    let half = backoff / 2;
+    Some(half + rand::thread_rng().gen_range(Duration::ZERO..=half))
+  }
+}
+
+/// Parse a `Retry-After` header: either a number of seconds or an HTTP date.
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+  let value = value.trim();
+  if let Ok(seconds) = value.parse::<u64>() {
+    return Some(Duration::from_secs(seconds));
+  }
+  let date = httpdate::parse_http_date(value).ok()?;
+  // A date in the past means "now".
+  Some(date.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_backoff_grows_and_is_capped() {
+    let policy = RetryPolicy {
+      max_retries: 10,
+      initial_backoff: Duration::from_millis(100),
+      max_backoff: Duration::from_millis(500),
+    };
+    let first = policy.delay(1, None).unwrap();
+    assert!(Duration::from_millis(50) <= first && first <= Duration::from_millis(100));
+    let third = policy.delay(3, None).unwrap();
+    assert!(Duration::from_millis(200) <= third && third <= Duration::from_millis(400));
+    let tenth = policy.delay(10, None).unwrap();
+    assert!(tenth <= Duration::from_millis(500));
+    assert_eq!(policy.delay(11, None), None);
+  }
+
+  #[test]
+  fn test_retry_after_replaces_backoff() {
+    let policy = RetryPolicy::default();
+    assert_eq!(
+      policy.delay(1, Some(Duration::from_secs(3))),
+      Some(Duration::from_secs(3))
+    );
+    // Longer than the policy is willing to wait.
+    assert_eq!(policy.delay(1, Some(Duration::from_secs(3600))), None);
+    assert_eq!(RetryPolicy::none().delay(1, Some(Duration::ZERO)), None);
+  }
+
+  #[test]
+  fn test_parse_retry_after() {
+    let now = httpdate::parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+    assert_eq!(
+      parse_retry_after(" 120 ", now),
+      Some(Duration::from_secs(120))
+    );
+    assert_eq!(
+      parse_retry_after("Sun, 06 Nov 1994 08:50:07 GMT", now),
+      Some(Duration::from_secs(30))
+    );
+    assert_eq!(
+      parse_retry_after("Sun, 06 Nov 1994 08:00:00 GMT", now),
+      Some(Duration::ZERO)
+    );
+    assert_eq!(parse_retry_after("soon", now), None);
+  }
+}
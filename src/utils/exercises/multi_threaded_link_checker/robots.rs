@@ -0,0 +1,376 @@
+// robots.txt support
+// Each host's robots.txt is fetched once and cached. The rules of the group
+// that names our product token apply, falling back to the `*` group.
+// Matching follows RFC 9309: the longest matching rule wins and `Allow`
+// wins a tie.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use reqwest::{StatusCode, Url};
+
+use super::fetch::Fetcher;
+use super::redirect::{self, RedirectPolicy};
+use super::{Error, USER_AGENT};
+
+/// RFC 9309 asks crawlers to read at least 500 KiB of a robots.txt.
+pub const MAX_ROBOTS_BYTES: u64 = 500 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+  allow: bool,
+  pattern: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Robots {
+  rules: Vec<Rule>,
+  crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+  /// Everything is allowed, used when a host has no robots.txt.
+  pub fn allow_all() -> Robots {
+    Robots::default()
+  }
+
+  /// Nothing is allowed, used when the server fails to serve robots.txt.
+  pub fn disallow_all() -> Robots {
+    Robots {
+      rules: vec![Rule {
+        allow: false,
+        pattern: String::from("/"),
+      }],
+      crawl_delay: None,
+    }
+  }
+
+  /// Parse a robots.txt body, keeping only the rules that apply to
+  /// `user_agent`. A group applies if it names exactly that product token,
+  /// ignoring case.
+  pub fn parse(text: &str, user_agent: &str) -> Robots {
+    let user_agent = user_agent.to_lowercase();
+    let mut specific: Option<Robots> = None;
+    let mut wildcard: Option<Robots> = None;
+
+    // Agents of the group being read, and whether its rules have started.
+    let mut group_agents: Vec<String> = Vec::new();
+    let mut in_rules = false;
+    let mut group = Robots::default();
+
+    let mut finish_group = |agents: &[String], group: Robots| {
+      for agent in agents {
+        let target = if agent == "*" {
+          &mut wildcard
+        } else if *agent == user_agent {
+          &mut specific
+        } else {
+          continue;
+        };
+        let merged = target.get_or_insert_with(Robots::default);
+        merged.rules.extend(group.rules.iter().cloned());
+        merged.crawl_delay = merged.crawl_delay.or(group.crawl_delay);
+      }
+    };
+
+    for line in text.lines() {
+      let line = line.split('#').next().unwrap_or_default().trim();
+      let Some((key, value)) = line.split_once(':') else {
+        continue;
+      };
+      let key = key.trim().to_lowercase();
+      let value = value.trim();
+      match key.as_str() {
+        "user-agent" => {
+          if in_rules {
+            finish_group(&group_agents, std::mem::take(&mut group));
+            group_agents.clear();
+            in_rules = false;
+          }
+          group_agents.push(value.to_lowercase());
+        }
+        "allow" | "disallow" => {
+          in_rules = true;
+          // An empty `Disallow:` means "allow everything" and adds no rule.
+          if !value.is_empty() {
+            group.rules.push(Rule {
+              allow: key == "allow",
+              pattern: value.to_string(),
+            });
+          }
+        }
+        "crawl-delay" => {
+          in_rules = true;
+          if let Ok(seconds) = value.parse::<f64>() {
+            if seconds.is_finite() && seconds >= 0.0 {
+              group.crawl_delay = Some(Duration::from_secs_f64(seconds));
+            }
+          }
+        }
+        _ => {}
+      }
+    }
+    finish_group(&group_agents, group);
+
+    specific.or(wildcard).unwrap_or_default()
+  }
+
+  /// Whether the path (and query) of `url` may be crawled.
+  pub fn is_allowed(&self, url: &Url) -> bool {
+    let mut path = url.path().to_string();
+    if let Some(query) = url.query() {
+      path.push('?');
+      path.push_str(query);
+    }
+
+    let mut best: Option<&Rule> = None;
+    for rule in self
+      .rules
+      .iter()
+      .filter(|rule| pattern_matches(&rule.pattern, &path))
+    {
+      best = match best {
+        Some(current)
+          if current.pattern.len() > rule.pattern.len()
+            || (current.pattern.len() == rule.pattern.len() && current.allow) =>
+        {
+          Some(current)
+        }
+        _ => Some(rule),
+      };
+    }
+    best.is_none_or(|rule| rule.allow)
+  }
+
+  pub fn crawl_delay(&self) -> Option<Duration> {
+    self.crawl_delay
+  }
+
+  /// What a robots.txt answered with `status` means, or `None` if its body
+  /// has to be read to tell. RFC 9309: a missing robots.txt allows
+  /// everything, while a server error means we should not crawl at all.
+  pub fn for_status(status: StatusCode) -> Option<Robots> {
+    if status.is_success() {
+      None
+    } else if status.is_client_error() {
+      Some(Robots::allow_all())
+    } else {
+      Some(Robots::disallow_all())
+    }
+  }
+
+  /// The rules in a robots.txt body, or none at all if it could not be
+  /// read.
+  pub fn from_body(text: Result<String, Error>) -> Robots {
+    match text {
+      Ok(text) => Robots::parse(&text, USER_AGENT),
+      Err(_) => Robots::disallow_all(),
+    }
+  }
+}
+
+/// Match a robots.txt path pattern: `*` matches any run of characters and
+/// a trailing `$` anchors the end. Without `$` a pattern is a prefix.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+  let (pattern, anchored) = match pattern.strip_suffix('$') {
+    Some(pattern) => (pattern, true),
+    None => (pattern, false),
+  };
+  let mut pieces = pattern.split('*');
+  let first = pieces.next().unwrap_or_default();
+  let Some(mut rest) = path.strip_prefix(first) else {
+    return false;
+  };
+  let pieces: Vec<&str> = pieces.collect();
+  for (idx, piece) in pieces.iter().enumerate() {
+    let is_last = idx == pieces.len() - 1;
+    if is_last && anchored {
+      return rest.ends_with(piece);
+    }
+    match rest.find(piece) {
+      Some(pos) => rest = &rest[pos + piece.len()..],
+      None => return false,
+    }
+  }
+  !anchored || rest.is_empty()
+}
+
+/// What the crawl policy decided for a single URL.
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+  Allowed,
+  Disallowed,
+}
+
+/// robots.txt rules and crawl-delay bookkeeping shared by all workers.
+#[derive(Default)]
+pub struct RobotsPolicy {
+  by_origin: Mutex<HashMap<String, Arc<Robots>>>,
+  next_request: Mutex<HashMap<String, Instant>>,
+}
+
+impl RobotsPolicy {
+  pub fn new() -> RobotsPolicy {
+    RobotsPolicy::default()
+  }
+
+  /// Check `url` against its host's robots.txt. For allowed URLs this also
+  /// sleeps until the host's crawl delay has passed.
+  pub fn check(&self, fetcher: &dyn Fetcher, url: &Url) -> Verdict {
+    let robots = self.robots_for(fetcher, url);
+    if !robots.is_allowed(url) {
+      return Verdict::Disallowed;
+    }
+    if let Some(delay) = robots.crawl_delay() {
+      self.wait_turn(url, delay);
+    }
+    Verdict::Allowed
+  }
+
+  fn robots_for(&self, fetcher: &dyn Fetcher, url: &Url) -> Arc<Robots> {
+    if let Some(robots) = self.cached(url) {
+      return robots;
+    }
+    // Fetch without holding the lock. Two workers may race to fetch the
+    // same file, which is harmless.
+    self.remember(url, fetch_robots(fetcher, url))
+  }
+
+  /// The robots.txt rules of the URL's host, if they were fetched already.
+  pub fn cached(&self, url: &Url) -> Option<Arc<Robots>> {
+    let origin = url.origin().ascii_serialization();
+    self.by_origin.lock().unwrap().get(&origin).cloned()
+  }
+
+  /// Keep `robots` as the rules of the URL's host, unless another fetch got
+  /// there first, and return the rules kept.
+  pub fn remember(&self, url: &Url, robots: Robots) -> Arc<Robots> {
+    let origin = url.origin().ascii_serialization();
+    self
+      .by_origin
+      .lock()
+      .unwrap()
+      .entry(origin)
+      .or_insert_with(|| Arc::new(robots))
+      .clone()
+  }
+
+  fn wait_turn(&self, url: &Url, delay: Duration) {
+    thread::sleep(self.reserve_turn(url, delay));
+  }
+
+  /// Reserve the next request slot for the URL's host, `delay` after the
+  /// previous one, and return how long to wait for it.
+  pub fn reserve_turn(&self, url: &Url, delay: Duration) -> Duration {
+    let origin = url.origin().ascii_serialization();
+    let now = Instant::now();
+    let mut next_request = self.next_request.lock().unwrap();
+    let slot = next_request.entry(origin).or_insert(now);
+    let start_at = (*slot).max(now);
+    *slot = start_at + delay;
+    start_at - now
+  }
+}
+
+fn fetch_robots(fetcher: &dyn Fetcher, url: &Url) -> Robots {
+  let Ok(robots_url) = url.join("/robots.txt") else {
+    return Robots::allow_all();
+  };
+  // RFC 9309 asks for at least five redirects to be followed.
+  match redirect::follow(fetcher, &robots_url, &RedirectPolicy::default()) {
+    Ok((response, _)) => match Robots::for_status(response.status) {
+      Some(robots) => robots,
+      None => Robots::from_body(response.text(MAX_ROBOTS_BYTES)),
+    },
+    // An unreachable host, or a broken redirect, is let through so that
+    // fetching the page itself reports the error instead of hiding it as
+    // "disallowed".
+    Err(_) => Robots::allow_all(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::check_links;
+  use super::super::config::CrawlConfig;
+  use super::super::test_server::TestServer;
+  use super::*;
+
+  fn url(path: &str) -> Url {
+    Url::parse("https://example.com")
+      .unwrap()
+      .join(path)
+      .unwrap()
+  }
+
+  #[test]
+  fn test_pattern_matches() {
+    assert!(pattern_matches("/private", "/private/page"));
+    assert!(pattern_matches("/*.pdf$", "/docs/a.pdf"));
+    assert!(!pattern_matches("/*.pdf$", "/docs/a.pdf?x=1"));
+    assert!(pattern_matches("/a*c", "/abbbc/d"));
+    assert!(!pattern_matches("/private", "/public"));
+  }
+
+  #[test]
+  fn test_longest_match_wins() {
+    let robots = Robots::parse(
+      "User-agent: *\nDisallow: /docs\nAllow: /docs/public\n",
+      USER_AGENT,
+    );
+    assert!(!robots.is_allowed(&url("/docs/secret")));
+    assert!(robots.is_allowed(&url("/docs/public/page")));
+    assert!(robots.is_allowed(&url("/other")));
+  }
+
+  #[test]
+  fn test_specific_group_overrides_wildcard() {
+    let text = format!(
+      "User-agent: *\nDisallow: /\n\nUser-agent: {USER_AGENT}\nDisallow: /admin\nCrawl-delay: 2\n"
+    );
+    let robots = Robots::parse(&text, USER_AGENT);
+    assert!(robots.is_allowed(&url("/page")));
+    assert!(!robots.is_allowed(&url("/admin")));
+    assert_eq!(robots.crawl_delay(), Some(Duration::from_secs(2)));
+  }
+
+  #[test]
+  fn test_only_our_product_token_matches() {
+    for agent in ["a", "link", "", "rust-wasm", "rust-wasm-link-checker-2"] {
+      let text = format!("User-agent: *\nDisallow: /\n\nUser-agent: {agent}\nAllow: /\n");
+      let robots = Robots::parse(&text, USER_AGENT);
+      assert!(!robots.is_allowed(&url("/page")), "User-agent: {agent}");
+    }
+    let robots = Robots::parse(
+      "User-agent: *\nDisallow: /\n\nUser-agent: Rust-Wasm-Link-Checker\nAllow: /\n",
+      USER_AGENT,
+    );
+    assert!(robots.is_allowed(&url("/page")));
+  }
+
+  #[test]
+  fn test_crawl_skips_disallowed_pages() {
+    let server = TestServer::new()
+      .route_with_headers(
+        "/robots.txt",
+        200,
+        &[("Content-Type", "text/plain")],
+        "User-agent: *\nDisallow: /private\nCrawl-delay: 0.1\n",
+      )
+      .route(
+        "/",
+        200,
+        r#"<a href="/private/a">a</a> <a href="/gone">b</a>"#,
+      )
+      .start();
+
+    let started = Instant::now();
+    let report = check_links(server.url("/"), CrawlConfig::default()).unwrap();
+
+    assert_eq!(report.bad_urls(), vec![server.url("/gone")]);
+    assert_eq!(report.disallowed_urls, vec![server.url("/private/a")]);
+    // Two allowed requests, one crawl delay apart.
+    assert!(started.elapsed() >= Duration::from_millis(100));
+  }
+}
@@ -0,0 +1,219 @@
+// Crawl scope
+// Which URLs belong to the site being crawled. Pages in scope have their
+// links followed; anything else is only checked, or skipped entirely when
+// external links are not checked. Hosts are compared as `Url` parses them,
+// so IP addresses and explicit ports work like any other host.
+//
+// On the command line and in config files a scope is written `exact`,
+// `subdomains`, `hosts=a.example,b.example:8080` or `path=/docs/`.
+use std::str::FromStr;
+
+use reqwest::Url;
+
+/// Second-level labels under which registrations happen one level deeper,
+/// as in `example.co.uk`. Not the full public suffix list, but the common
+/// cases.
+const TWO_LABEL_SUFFIXES: &[&str] = &[
+  "ac", "co", "com", "edu", "gov", "ltd", "me", "ne", "net", "or", "org", "plc",
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ScopePolicy {
+  /// The start URL's host and port only.
+  #[default]
+  ExactHost,
+  /// The start URL's registrable domain and all its subdomains, so
+  /// `docs.example.com` is part of a crawl started at `www.example.com`.
+  Subdomains,
+  /// Only these hosts, each `host` or `host:port`. A host without a port
+  /// matches any port.
+  Hosts(Vec<String>),
+  /// The start URL's host and port, below this path prefix.
+  PathPrefix(String),
+}
+
+impl ScopePolicy {
+  /// Whether `url` is part of the site crawled from `start_url`.
+  pub fn contains(&self, start_url: &Url, url: &Url) -> bool {
+    if url.host().is_none() {
+      return false;
+    }
+    match self {
+      ScopePolicy::ExactHost => same_host(start_url, url),
+      ScopePolicy::Subdomains => match (start_url.domain(), url.domain()) {
+        (Some(start), Some(host)) => {
+          let site = registrable_domain(start);
+          host == site
+            || host
+              .strip_suffix(site)
+              .is_some_and(|sub| sub.ends_with('.'))
+        }
+        // IP addresses have no subdomains.
+        _ => same_host(start_url, url),
+      },
+      ScopePolicy::Hosts(hosts) => hosts.iter().any(|allowed| host_matches(allowed, url)),
+      ScopePolicy::PathPrefix(prefix) => {
+        same_host(start_url, url) && url.path().starts_with(prefix.as_str())
+      }
+    }
+  }
+}
+
+impl FromStr for ScopePolicy {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<ScopePolicy, String> {
+    match text.split_once('=') {
+      None if text == "exact" => Ok(ScopePolicy::ExactHost),
+      None if text == "subdomains" => Ok(ScopePolicy::Subdomains),
+      Some(("hosts", hosts)) => {
+        let hosts: Vec<String> = hosts
+          .split(',')
+          .map(|host| host.trim().to_string())
+          .collect();
+        if hosts
+          .iter()
+          .any(|host| host.is_empty() || host.contains('/'))
+        {
+          return Err(format!("{text:?} needs a comma-separated list of hosts"));
+        }
+        Ok(ScopePolicy::Hosts(hosts))
+      }
+      Some(("path", prefix)) if prefix.starts_with('/') => {
+        Ok(ScopePolicy::PathPrefix(prefix.to_string()))
+      }
+      Some(("path", _)) => Err(format!("{text:?} needs a path starting with /")),
+      _ => Err(format!(
+        "{text:?} is not `exact`, `subdomains`, `hosts=...` or `path=...`"
+      )),
+    }
+  }
+}
+
+/// Same host and same explicit port. Default ports are not compared, so
+/// `http://example.com` and `https://example.com` are one site.
+fn same_host(a: &Url, b: &Url) -> bool {
+  a.host() == b.host() && a.port() == b.port()
+}
+
+fn host_matches(allowed: &str, url: &Url) -> bool {
+  let Some(host) = url.host_str() else {
+    return false;
+  };
+  match url.port() {
+    Some(port) if allowed.contains(':') && !allowed.ends_with(']') => {
+      allowed.eq_ignore_ascii_case(&format!("{host}:{port}"))
+    }
+    _ => allowed.eq_ignore_ascii_case(host),
+  }
+}
+
+/// The part of `domain` its owner registered: `www.example.com` gives
+/// `example.com`, `www.example.co.uk` gives `example.co.uk`.
+fn registrable_domain(domain: &str) -> &str {
+  let labels: Vec<&str> = domain.split('.').collect();
+  let kept = match labels.as_slice() {
+    [.., second, tld] if tld.len() == 2 && TWO_LABEL_SUFFIXES.contains(second) => 3,
+    _ => 2,
+  };
+  if labels.len() <= kept {
+    return domain;
+  }
+  let dropped: usize = labels[..labels.len() - kept]
+    .iter()
+    .map(|label| label.len() + 1)
+    .sum();
+  &domain[dropped..]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn url(url: &str) -> Url {
+    Url::parse(url).unwrap()
+  }
+
+  #[test]
+  fn test_parse() {
+    assert_eq!("exact".parse(), Ok(ScopePolicy::ExactHost));
+    assert_eq!("subdomains".parse(), Ok(ScopePolicy::Subdomains));
+    assert_eq!(
+      "hosts=example.com, cdn.example.com:8080".parse(),
+      Ok(ScopePolicy::Hosts(vec![
+        String::from("example.com"),
+        String::from("cdn.example.com:8080")
+      ]))
+    );
+    assert_eq!(
+      "path=/docs/".parse(),
+      Ok(ScopePolicy::PathPrefix(String::from("/docs/")))
+    );
+    for invalid in [
+      "",
+      "everything",
+      "hosts=",
+      "hosts=a.com,,b.com",
+      "path=docs",
+    ] {
+      assert!(invalid.parse::<ScopePolicy>().is_err(), "{invalid}");
+    }
+  }
+
+  #[test]
+  fn test_registrable_domain() {
+    assert_eq!(registrable_domain("www.example.com"), "example.com");
+    assert_eq!(registrable_domain("a.b.example.co.uk"), "example.co.uk");
+    assert_eq!(registrable_domain("example.com"), "example.com");
+    assert_eq!(registrable_domain("localhost"), "localhost");
+  }
+
+  #[test]
+  fn test_scope_modes() {
+    let start = url("https://www.example.com/docs/");
+    let cases = [
+      ("https://www.example.com/a", [true, true, false, false]),
+      ("http://www.example.com/docs/b", [true, true, false, true]),
+      (
+        "https://www.example.com:8443/docs/b",
+        [false, true, true, false],
+      ),
+      ("https://docs.example.com/", [false, true, true, false]),
+      (
+        "https://example.com.evil.test/",
+        [false, false, false, false],
+      ),
+      ("mailto:someone@example.com", [false, false, false, false]),
+    ];
+    let policies = [
+      ScopePolicy::ExactHost,
+      ScopePolicy::Subdomains,
+      ScopePolicy::Hosts(vec![
+        String::from("docs.example.com"),
+        String::from("www.example.com:8443"),
+      ]),
+      ScopePolicy::PathPrefix(String::from("/docs/")),
+    ];
+    for (link, expected) in cases {
+      for (policy, expected) in policies.iter().zip(expected) {
+        assert_eq!(
+          policy.contains(&start, &url(link)),
+          expected,
+          "{policy:?} {link}"
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn test_ip_hosts_and_ports() {
+    let start = url("http://127.0.0.1:8080/");
+    for policy in [ScopePolicy::ExactHost, ScopePolicy::Subdomains] {
+      assert!(policy.contains(&start, &url("http://127.0.0.1:8080/a")));
+      assert!(!policy.contains(&start, &url("http://127.0.0.1:9090/a")));
+      assert!(!policy.contains(&start, &url("http://127.0.0.2:8080/a")));
+    }
+    let hosts = ScopePolicy::Hosts(vec![String::from("[::1]")]);
+    assert!(hosts.contains(&start, &url("http://[::1]:3000/")));
+  }
+}
@@ -0,0 +1,273 @@
+// Sitemaps
+// A sitemap lists the pages a site wants crawled, including ones no page
+// links to. Its entries seed the crawl next to the start URL; an entry that
+// turns out to be unreachable by links is an orphan. Sitemap indexes point
+// to further sitemaps, and any of them may be gzipped.
+use std::collections::HashSet;
+use std::io::Read;
+use std::time::Duration;
+
+use flate2::read::GzDecoder;
+use reqwest::Url;
+
+use super::extract::ResourceKind;
+use super::fetch::Fetcher;
+use super::redirect::{self, RedirectPolicy};
+use super::report::CheckedUrl;
+use super::{Error, HostPolicies, Link, LinkSource};
+
+/// The sitemaps.org limit for one uncompressed sitemap.
+const MAX_SITEMAP_BYTES: u64 = 50 * 1024 * 1024;
+
+/// What a sitemap file lists.
+#[derive(Debug, PartialEq)]
+enum Sitemap {
+  /// `<urlset>`: pages.
+  Pages(Vec<Url>),
+  /// `<sitemapindex>`: more sitemaps.
+  Index(Vec<Url>),
+}
+
+/// Everything read from a set of sitemaps.
+#[derive(Debug, Default)]
+pub struct SitemapEntries {
+  /// Every page listed, as a link from the sitemap listing it.
+  pub links: Vec<Link>,
+  /// Sitemaps that could not be read.
+  pub failures: Vec<CheckedUrl>,
+  /// Sitemaps robots.txt kept us from reading.
+  pub disallowed: Vec<Url>,
+}
+
+/// Read `sitemaps` and every sitemap their indexes point to. Like pages,
+/// they are only fetched if robots.txt allows it and the host's rate limit
+/// has room.
+pub fn read_sitemaps(
+  fetcher: &dyn Fetcher,
+  sitemaps: &[Url],
+  policy: &RedirectPolicy,
+  policies: &HostPolicies,
+) -> SitemapEntries {
+  let mut entries = SitemapEntries::default();
+  let mut pending = sitemaps.to_vec();
+  let mut seen = HashSet::new();
+  while let Some(sitemap_url) = pending.pop() {
+    if !seen.insert(sitemap_url.clone()) {
+      continue;
+    }
+    eprintln!("Reading sitemap {sitemap_url:#}");
+    match fetch_sitemap(fetcher, &sitemap_url, policy, policies) {
+      Ok(Sitemap::Pages(pages)) => {
+        entries.links.extend(pages.into_iter().map(|url| Link {
+          url,
+          source: LinkSource {
+            page: sitemap_url.clone(),
+            kind: ResourceKind::SitemapEntry,
+            element: String::from("loc"),
+            text: String::new(),
+          },
+        }));
+      }
+      Ok(Sitemap::Index(sitemaps)) => pending.extend(sitemaps),
+      Err(Error::DisallowedByRobots) => {
+        eprintln!("Skipping sitemap {sitemap_url:#}: disallowed by robots.txt");
+        entries.disallowed.push(sitemap_url);
+      }
+      Err(error) => {
+        eprintln!("Could not read sitemap {sitemap_url:#}: {error:#}");
+        entries.failures.push(CheckedUrl {
+          status: error.status(),
+          url: sitemap_url,
+          redirect_chain: Vec::new(),
+          elapsed: Duration::ZERO,
+          sources: Vec::new(),
+          error: Some(error),
+        });
+      }
+    }
+  }
+  entries
+}
+
+fn fetch_sitemap(
+  fetcher: &dyn Fetcher,
+  url: &Url,
+  policy: &RedirectPolicy,
+  policies: &HostPolicies,
+) -> Result<Sitemap, Error> {
+  policies.admit(fetcher, url)?;
+  let (response, _) = redirect::follow(fetcher, url, policy)?;
+  if !response.status.is_success() {
+    return Err(Error::bad_response(response.status, &response.headers));
+  }
+  let bytes = response.bytes(MAX_SITEMAP_BYTES)?;
+  parse_sitemap(&decompress(bytes)?)
+}
+
+/// Gunzip `bytes` if they are gzipped. `.xml.gz` files are usually served
+/// as they are rather than with a `Content-Encoding`, so the content is
+/// what tells.
+fn decompress(bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
+  if !bytes.starts_with(&[0x1f, 0x8b]) {
+    return Ok(bytes);
+  }
+  let mut xml = Vec::new();
+  GzDecoder::new(bytes.as_slice())
+    .take(MAX_SITEMAP_BYTES)
+    .read_to_end(&mut xml)?;
+  Ok(xml)
+}
+
+fn parse_sitemap(xml: &[u8]) -> Result<Sitemap, Error> {
+  let xml = std::str::from_utf8(xml).map_err(|error| Error::InvalidSitemap(error.to_string()))?;
+  let document =
+    roxmltree::Document::parse(xml).map_err(|error| Error::InvalidSitemap(error.to_string()))?;
+  let root = document.root_element();
+  let entry_tag = match root.tag_name().name() {
+    "urlset" => "url",
+    "sitemapindex" => "sitemap",
+    other => return Err(Error::InvalidSitemap(format!("unexpected <{other}>"))),
+  };
+  // Entries that are not absolute URLs are invalid and ignored.
+  let urls = root
+    .children()
+    .filter(|entry| entry.tag_name().name() == entry_tag)
+    .filter_map(|entry| {
+      entry
+        .children()
+        .find(|child| child.tag_name().name() == "loc")
+    })
+    .filter_map(|loc| Url::parse(loc.text()?.trim()).ok())
+    .collect();
+  Ok(match entry_tag {
+    "url" => Sitemap::Pages(urls),
+    _ => Sitemap::Index(urls),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+
+  use flate2::write::GzEncoder;
+  use flate2::Compression;
+
+  use super::super::config::CrawlConfig;
+  use super::super::fetch::MemoryFetcher;
+  use super::*;
+
+  fn url(url: &str) -> Url {
+    Url::parse(url).unwrap()
+  }
+
+  #[test]
+  fn test_parse_urlset_and_index() {
+    let urlset = br#"<?xml version="1.0" encoding="UTF-8"?>
+      <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+        <url><loc> https://example.com/a </loc><lastmod>2023-01-01</lastmod></url>
+        <url><loc>not a url</loc></url>
+        <url><loc>https://example.com/b?x=1&amp;y=2</loc></url>
+      </urlset>"#;
+    assert_eq!(
+      parse_sitemap(urlset).unwrap(),
+      Sitemap::Pages(vec![
+        url("https://example.com/a"),
+        url("https://example.com/b?x=1&y=2")
+      ])
+    );
+
+    let index = br#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+        <sitemap><loc>https://example.com/pages.xml.gz</loc></sitemap>
+      </sitemapindex>"#;
+    assert_eq!(
+      parse_sitemap(index).unwrap(),
+      Sitemap::Index(vec![url("https://example.com/pages.xml.gz")])
+    );
+    assert!(matches!(
+      parse_sitemap(b"<html></html>"),
+      Err(Error::InvalidSitemap(_))
+    ));
+  }
+
+  #[test]
+  fn test_indexes_and_gzipped_sitemaps_are_followed() {
+    let pages = r#"<urlset><url><loc>https://example.com/orphan</loc></url></urlset>"#;
+    let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+    gzip.write_all(pages.as_bytes()).unwrap();
+    let fetcher = MemoryFetcher::new()
+      .response(
+        "https://example.com/sitemap.xml",
+        200,
+        &[("Content-Type", "application/xml")],
+        r#"<sitemapindex>
+          <sitemap><loc>https://example.com/pages.xml.gz</loc></sitemap>
+          <sitemap><loc>https://example.com/gone.xml</loc></sitemap>
+          <sitemap><loc>https://example.com/sitemap.xml</loc></sitemap>
+        </sitemapindex>"#,
+      )
+      .bytes(
+        "https://example.com/pages.xml.gz",
+        200,
+        &[("Content-Type", "application/gzip")],
+        &gzip.finish().unwrap(),
+      );
+
+    let entries = read_sitemaps(
+      &fetcher,
+      &[url("https://example.com/sitemap.xml")],
+      &RedirectPolicy::default(),
+      &HostPolicies::new(&CrawlConfig::default()),
+    );
+
+    assert_eq!(entries.links.len(), 1);
+    assert_eq!(entries.links[0].url, url("https://example.com/orphan"));
+    assert_eq!(
+      entries.links[0].source.page,
+      url("https://example.com/pages.xml.gz")
+    );
+    assert_eq!(entries.failures.len(), 1);
+    assert_eq!(entries.failures[0].url, url("https://example.com/gone.xml"));
+    // The index lists itself, but is only read once.
+    let sitemap_fetches = fetcher
+      .requests()
+      .into_iter()
+      .filter(|url| url.path() != "/robots.txt")
+      .count();
+    assert_eq!(sitemap_fetches, 3);
+  }
+
+  #[test]
+  fn test_robots_txt_applies_to_sitemaps() {
+    let fetcher = MemoryFetcher::new()
+      .response(
+        "https://example.com/robots.txt",
+        200,
+        &[("Content-Type", "text/plain")],
+        "User-agent: *\nDisallow: /private/\n",
+      )
+      .response(
+        "https://example.com/sitemap.xml",
+        200,
+        &[("Content-Type", "application/xml")],
+        r#"<sitemapindex>
+          <sitemap><loc>https://example.com/private/pages.xml</loc></sitemap>
+        </sitemapindex>"#,
+      );
+
+    let entries = read_sitemaps(
+      &fetcher,
+      &[url("https://example.com/sitemap.xml")],
+      &RedirectPolicy::default(),
+      &HostPolicies::new(&CrawlConfig::default()),
+    );
+
+    assert!(entries.failures.is_empty());
+    assert_eq!(
+      entries.disallowed,
+      vec![url("https://example.com/private/pages.xml")]
+    );
+    assert!(!fetcher
+      .requests()
+      .contains(&url("https://example.com/private/pages.xml")));
+  }
+}
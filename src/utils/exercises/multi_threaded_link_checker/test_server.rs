@@ -0,0 +1,153 @@
+// A tiny HTTP stand-in for the link checker tests.
+// It answers every request from a fixed table of routes, so crawls can be
+// exercised without touching the network.
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use reqwest::Url;
+
+#[derive(Clone, Debug)]
+struct Route {
+  status: u16,
+  headers: Vec<(String, String)>,
+  body: String,
+}
+
+#[derive(Default)]
+pub struct TestServer {
+  routes: HashMap<String, Route>,
+  /// Answers given instead of the route until they run out.
+  failures: HashMap<String, (AtomicUsize, Route)>,
+}
+
+pub struct RunningServer {
+  addr: SocketAddr,
+}
+
+impl TestServer {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Serve `body` as HTML with the given status for `path`.
+  pub fn route(self, path: &str, status: u16, body: &str) -> Self {
+    self.route_with_headers(path, status, &[("Content-Type", "text/html")], body)
+  }
+
+  pub fn route_with_headers(
+    mut self,
+    path: &str,
+    status: u16,
+    headers: &[(&str, &str)],
+    body: &str,
+  ) -> Self {
+    let headers = headers
+      .iter()
+      .map(|(name, value)| (name.to_string(), value.to_string()))
+      .collect();
+    let route = Route {
+      status,
+      headers,
+      body: body.to_string(),
+    };
+    self.routes.insert(path.to_string(), route);
+    self
+  }
+
+  /// Answer the first `times` requests for `path` with `status` and
+  /// `headers` before falling back to its route.
+  pub fn fail_first(
+    mut self,
+    path: &str,
+    times: usize,
+    status: u16,
+    headers: &[(&str, &str)],
+  ) -> Self {
+    let headers = headers
+      .iter()
+      .map(|(name, value)| (name.to_string(), value.to_string()))
+      .collect();
+    let failure = Route {
+      status,
+      headers,
+      body: String::new(),
+    };
+    self
+      .failures
+      .insert(path.to_string(), (AtomicUsize::new(times), failure));
+    self
+  }
+
+  /// Bind to a free local port and answer requests on a background thread.
+  /// Unknown paths get a 404.
+  pub fn start(self) -> RunningServer {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Arc::new(self);
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+          continue;
+        };
+        let server = server.clone();
+        thread::spawn(move || respond(stream, &server));
+      }
+    });
+    RunningServer { addr }
+  }
+}
+
+impl RunningServer {
+  /// An absolute URL on this server.
+  pub fn url(&self, path: &str) -> Url {
+    Url::parse(&format!("http://{}{path}", self.addr)).unwrap()
+  }
+}
+
+fn respond(mut stream: TcpStream, server: &TestServer) {
+  let mut reader = BufReader::new(stream.try_clone().unwrap());
+  let mut request_line = String::new();
+  if reader.read_line(&mut request_line).is_err() {
+    return;
+  }
+  // Drain the headers, we never look at them.
+  loop {
+    let mut line = String::new();
+    match reader.read_line(&mut line) {
+      Ok(0) | Err(_) => break,
+      Ok(_) if line == "\r\n" => break,
+      Ok(_) => {}
+    }
+  }
+
+  let mut parts = request_line.split_whitespace();
+  let method = parts.next().unwrap_or_default();
+  let path = parts.next().unwrap_or("/");
+  let not_found = Route {
+    status: 404,
+    headers: Vec::new(),
+    body: String::from("not found"),
+  };
+  let failure = server.failures.get(path).and_then(|(left, failure)| {
+    let took = left.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+      left.checked_sub(1)
+    });
+    took.is_ok().then_some(failure)
+  });
+  let route = failure.unwrap_or_else(|| server.routes.get(path).unwrap_or(&not_found));
+
+  let mut response = format!("HTTP/1.1 {} Test\r\n", route.status);
+  for (name, value) in &route.headers {
+    response.push_str(&format!("{name}: {value}\r\n"));
+  }
+  response.push_str(&format!("Content-Length: {}\r\n", route.body.len()));
+  response.push_str("Connection: close\r\n\r\n");
+  if method != "HEAD" {
+    response.push_str(&route.body);
+  }
+  let _ = stream.write_all(response.as_bytes());
+}
@@ -0,0 +1,422 @@
+// Matrix
+// A dense matrix whose shape is only known at runtime, stored row-major in
+// one `Vec`: element (row, col) lives at `row * cols + col`. Rows are
+// contiguous slices; columns are strided views over the same storage.
+use std::fmt;
+use std::iter::StepBy;
+use std::ops::{Index, IndexMut};
+use std::slice;
+
+use thiserror::Error;
+
+pub mod arithmetic;
+pub mod lu;
+pub mod pretty;
+pub mod sparse;
+#[cfg(test)]
+pub mod testing;
+pub mod transpose;
+
+pub use arithmetic::Scalar;
+use pretty::PrettyPrinter;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MatrixError {
+  #[error("row {row} has {len} elements, expected {expected}")]
+  RaggedRow {
+    row: usize,
+    len: usize,
+    expected: usize,
+  },
+  #[error("{len} elements do not fill a {rows}x{cols} matrix")]
+  WrongLength {
+    rows: usize,
+    cols: usize,
+    len: usize,
+  },
+  /// The shapes do not fit the operation, e.g. adding a 2x3 and a 3x2
+  /// matrix or multiplying two 2x3 matrices.
+  #[error(
+    "cannot {operation} a {}x{} and a {}x{} matrix",
+    left.0,
+    left.1,
+    right.0,
+    right.1
+  )]
+  DimensionMismatch {
+    operation: &'static str,
+    left: (usize, usize),
+    right: (usize, usize),
+  },
+  #[error("({row}, {col}) out of bounds for a {rows}x{cols} matrix")]
+  OutOfBounds {
+    row: usize,
+    col: usize,
+    rows: usize,
+    cols: usize,
+  },
+  #[error("a {rows}x{cols} matrix is not square")]
+  NotSquare { rows: usize, cols: usize },
+  #[error("the matrix is singular")]
+  Singular,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Matrix<T> {
+  rows: usize,
+  cols: usize,
+  data: Vec<T>,
+}
+
+impl<T> Matrix<T> {
+  /// A `rows` x `cols` matrix from its elements in row-major order.
+  pub fn from_vec(rows: usize, cols: usize, data: Vec<T>) -> Result<Matrix<T>, MatrixError> {
+    if rows.checked_mul(cols) != Some(data.len()) {
+      return Err(MatrixError::WrongLength {
+        rows,
+        cols,
+        len: data.len(),
+      });
+    }
+    Ok(Matrix { rows, cols, data })
+  }
+
+  /// A matrix whose element (row, col) is `f(row, col)`.
+  pub fn from_fn(rows: usize, cols: usize, mut f: impl FnMut(usize, usize) -> T) -> Matrix<T> {
+    let data = (0..rows)
+      .flat_map(|row| (0..cols).map(move |col| (row, col)))
+      .map(|(row, col)| f(row, col))
+      .collect();
+    Matrix { rows, cols, data }
+  }
+
+  /// A matrix from its rows, which must all have the same length. No rows
+  /// give a 0x0 matrix.
+  pub fn from_rows<R>(rows: &[R]) -> Result<Matrix<T>, MatrixError>
+  where
+    R: AsRef<[T]>,
+    T: Clone,
+  {
+    let cols = rows.first().map_or(0, |row| row.as_ref().len());
+    let mut data = Vec::with_capacity(rows.len() * cols);
+    for (index, row) in rows.iter().enumerate() {
+      let row = row.as_ref();
+      if row.len() != cols {
+        return Err(MatrixError::RaggedRow {
+          row: index,
+          len: row.len(),
+          expected: cols,
+        });
+      }
+      data.extend_from_slice(row);
+    }
+    Ok(Matrix {
+      rows: rows.len(),
+      cols,
+      data,
+    })
+  }
+
+  pub fn rows(&self) -> usize {
+    self.rows
+  }
+
+  pub fn cols(&self) -> usize {
+    self.cols
+  }
+
+  /// `(rows, cols)`.
+  pub fn shape(&self) -> (usize, usize) {
+    (self.rows, self.cols)
+  }
+
+  pub fn is_square(&self) -> bool {
+    self.rows == self.cols
+  }
+
+  pub fn get(&self, row: usize, col: usize) -> Option<&T> {
+    if row < self.rows && col < self.cols {
+      self.data.get(row * self.cols + col)
+    } else {
+      None
+    }
+  }
+
+  pub fn get_mut(&mut self, row: usize, col: usize) -> Option<&mut T> {
+    if row < self.rows && col < self.cols {
+      self.data.get_mut(row * self.cols + col)
+    } else {
+      None
+    }
+  }
+
+  /// Row `row` as a slice. Panics if it is out of bounds.
+  pub fn row(&self, row: usize) -> &[T] {
+    assert!(
+      row < self.rows,
+      "row {row} out of bounds for {} rows",
+      self.rows
+    );
+    &self.data[row * self.cols..(row + 1) * self.cols]
+  }
+
+  pub fn row_mut(&mut self, row: usize) -> &mut [T] {
+    assert!(
+      row < self.rows,
+      "row {row} out of bounds for {} rows",
+      self.rows
+    );
+    &mut self.data[row * self.cols..(row + 1) * self.cols]
+  }
+
+  /// The elements of column `col`, top to bottom. Panics if it is out of
+  /// bounds.
+  pub fn column(&self, col: usize) -> Column<'_, T> {
+    assert!(
+      col < self.cols,
+      "column {col} out of bounds for {} columns",
+      self.cols
+    );
+    Column(self.data[col..].iter().step_by(self.cols))
+  }
+
+  /// Swap rows `a` and `b`. Panics if either is out of bounds.
+  pub fn swap_rows(&mut self, a: usize, b: usize) {
+    assert!(
+      a < self.rows && b < self.rows,
+      "rows {a} and {b} out of bounds for {} rows",
+      self.rows
+    );
+    for col in 0..self.cols {
+      self.data.swap(a * self.cols + col, b * self.cols + col);
+    }
+  }
+
+  /// Every row, top to bottom.
+  pub fn row_iter(&self) -> impl ExactSizeIterator<Item = &[T]> + '_ {
+    (0..self.rows).map(move |row| self.row(row))
+  }
+
+  /// Every column, left to right.
+  pub fn column_iter(&self) -> impl ExactSizeIterator<Item = Column<'_, T>> + '_ {
+    (0..self.cols).map(move |col| self.column(col))
+  }
+
+  /// The elements in row-major order.
+  pub fn as_slice(&self) -> &[T] {
+    &self.data
+  }
+
+  pub fn into_vec(self) -> Vec<T> {
+    self.data
+  }
+
+  /// The `cols` x `rows` matrix with rows and columns swapped. See
+  /// `transpose_blocked` and `transpose_in_place` for large matrices.
+  pub fn transpose(&self) -> Matrix<T>
+  where
+    T: Clone,
+  {
+    Matrix::from_fn(self.cols, self.rows, |row, col| self[(col, row)].clone())
+  }
+
+  /// A matrix of the same shape with `f` applied to every element.
+  pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Matrix<U> {
+    Matrix {
+      rows: self.rows,
+      cols: self.cols,
+      data: self.data.iter().map(f).collect(),
+    }
+  }
+}
+
+impl<T: Clone> Matrix<T> {
+  /// A `rows` x `cols` matrix with every element set to `value`.
+  pub fn filled(rows: usize, cols: usize, value: T) -> Matrix<T> {
+    Matrix {
+      rows,
+      cols,
+      data: vec![value; rows * cols],
+    }
+  }
+}
+
+/// The elements of one column, see `Matrix::column`.
+#[derive(Debug, Clone)]
+pub struct Column<'a, T>(StepBy<slice::Iter<'a, T>>);
+
+impl<'a, T> Iterator for Column<'a, T> {
+  type Item = &'a T;
+
+  fn next(&mut self) -> Option<&'a T> {
+    self.0.next()
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.0.size_hint()
+  }
+}
+
+impl<T> ExactSizeIterator for Column<'_, T> {}
+
+impl<T> Index<(usize, usize)> for Matrix<T> {
+  type Output = T;
+
+  fn index(&self, (row, col): (usize, usize)) -> &T {
+    match self.get(row, col) {
+      Some(element) => element,
+      None => panic!(
+        "({row}, {col}) out of bounds for a {}x{} matrix",
+        self.rows, self.cols
+      ),
+    }
+  }
+}
+
+impl<T> IndexMut<(usize, usize)> for Matrix<T> {
+  fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut T {
+    let (rows, cols) = self.shape();
+    match self.get_mut(row, col) {
+      Some(element) => element,
+      None => panic!("({row}, {col}) out of bounds for a {rows}x{cols} matrix"),
+    }
+  }
+}
+
+impl<T, const R: usize, const C: usize> From<[[T; C]; R]> for Matrix<T> {
+  fn from(rows: [[T; C]; R]) -> Matrix<T> {
+    Matrix {
+      rows: R,
+      cols: C,
+      data: rows.into_iter().flatten().collect(),
+    }
+  }
+}
+
+impl<T> TryFrom<Vec<Vec<T>>> for Matrix<T> {
+  type Error = MatrixError;
+
+  fn try_from(rows: Vec<Vec<T>>) -> Result<Matrix<T>, MatrixError> {
+    let row_count = rows.len();
+    let cols = rows.first().map_or(0, Vec::len);
+    let mut data = Vec::with_capacity(row_count * cols);
+    for (index, row) in rows.into_iter().enumerate() {
+      if row.len() != cols {
+        return Err(MatrixError::RaggedRow {
+          row: index,
+          len: row.len(),
+          expected: cols,
+        });
+      }
+      data.extend(row);
+    }
+    Ok(Matrix {
+      rows: row_count,
+      cols,
+      data,
+    })
+  }
+}
+
+/// One row per line with the columns aligned, see `PrettyPrinter`. A
+/// precision, as in `{:.2}`, applies to every element.
+impl<T: fmt::Display + Clone> fmt::Display for Matrix<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let printer = PrettyPrinter {
+      precision: f.precision(),
+      ..PrettyPrinter::default()
+    };
+    f.write_str(&printer.render(self))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_construction() {
+    let from_array = Matrix::from([[1, 2, 3], [4, 5, 6]]);
+    let from_slices = Matrix::from_rows(&[&[1, 2, 3][..], &[4, 5, 6]]).unwrap();
+    let from_vecs = Matrix::try_from(vec![vec![1, 2, 3], vec![4, 5, 6]]).unwrap();
+    let from_fn = Matrix::from_fn(2, 3, |row, col| row * 3 + col + 1);
+
+    assert_eq!(from_array.shape(), (2, 3));
+    assert_eq!(from_slices, from_array);
+    assert_eq!(from_vecs, from_array);
+    assert_eq!(from_fn, from_array);
+    assert_eq!(
+      Matrix::from_vec(2, 3, vec![1, 2, 3, 4, 5, 6]).unwrap(),
+      from_array
+    );
+    assert_eq!(
+      Matrix::<i32>::from_rows::<Vec<i32>>(&[]).unwrap().shape(),
+      (0, 0)
+    );
+  }
+
+  #[test]
+  fn test_shape_errors() {
+    assert_eq!(
+      Matrix::try_from(vec![vec![1, 2], vec![3]]),
+      Err(MatrixError::RaggedRow {
+        row: 1,
+        len: 1,
+        expected: 2
+      })
+    );
+    assert_eq!(
+      Matrix::from_vec(2, 2, vec![1, 2, 3]),
+      Err(MatrixError::WrongLength {
+        rows: 2,
+        cols: 2,
+        len: 3
+      })
+    );
+  }
+
+  #[test]
+  fn test_indexing_and_iterators() {
+    let mut matrix = Matrix::from([[1, 2, 3], [4, 5, 6]]);
+    matrix[(1, 2)] = 60;
+
+    assert_eq!(matrix[(0, 1)], 2);
+    assert_eq!(matrix.get(2, 0), None);
+    assert_eq!(matrix.get(0, 3), None);
+    assert_eq!(matrix.row(1), &[4, 5, 60]);
+    let columns: Vec<Vec<i32>> = matrix
+      .column_iter()
+      .map(|column| column.copied().collect())
+      .collect();
+    assert_eq!(columns, vec![vec![1, 4], vec![2, 5], vec![3, 60]]);
+    assert_eq!(matrix.column(2).len(), 2);
+    assert_eq!(matrix.row_iter().len(), 2);
+  }
+
+  #[test]
+  #[should_panic(expected = "(2, 0) out of bounds for a 2x3 matrix")]
+  fn test_index_out_of_bounds() {
+    let matrix = Matrix::from([[1, 2, 3], [4, 5, 6]]);
+    let _ = matrix[(2, 0)];
+  }
+
+  #[test]
+  fn test_transpose_non_square() {
+    let matrix = Matrix::from([["a", "b", "c"], ["d", "e", "f"]]);
+    let transposed = matrix.transpose();
+
+    assert_eq!(
+      transposed,
+      Matrix::from([["a", "d"], ["b", "e"], ["c", "f"]])
+    );
+    assert_eq!(transposed.transpose(), matrix);
+  }
+
+  #[test]
+  fn test_display_aligns_columns() {
+    let matrix = Matrix::from([[1, 2], [3, 4]]);
+    assert_eq!(matrix.to_string(), "[1, 2]\n[3, 4]\n");
+    let matrix = Matrix::from([[1.5, -2.0], [30.0, 4.25]]);
+    assert_eq!(format!("{matrix:.1}"), "[ 1.5, -2.0]\n[30.0,  4.2]\n");
+  }
+}
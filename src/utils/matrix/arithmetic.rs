@@ -0,0 +1,302 @@
+// Arithmetic
+// Element-wise addition and subtraction, scaling, and matrix products. The
+// operators panic when the shapes do not fit, as indexing out of bounds
+// does; the `checked_` methods return the mismatch as an error instead:
+//
+//   let sum = &a + &b;
+//   let product = a.checked_mul(&b)?;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+use super::{Matrix, MatrixError};
+
+/// The numbers matrices do arithmetic on.
+pub trait Scalar:
+  Copy + PartialEq + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self>
+{
+  const ZERO: Self;
+  const ONE: Self;
+}
+
+macro_rules! impl_scalar {
+  ($zero:literal, $one:literal: $($ty:ty),*) => {
+    $(
+      impl Scalar for $ty {
+        const ZERO: $ty = $zero;
+        const ONE: $ty = $one;
+      }
+    )*
+  };
+}
+
+impl_scalar!(0, 1: i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+impl_scalar!(0.0, 1.0: f32, f64);
+
+impl<T: Scalar> Matrix<T> {
+  /// The `size` x `size` identity matrix.
+  pub fn identity(size: usize) -> Matrix<T> {
+    let mut identity = Matrix::filled(size, size, T::ZERO);
+    for i in 0..size {
+      identity[(i, i)] = T::ONE;
+    }
+    identity
+  }
+
+  pub fn checked_add(&self, other: &Matrix<T>) -> Result<Matrix<T>, MatrixError> {
+    self.zip_with("add", other, |a, b| a + b)
+  }
+
+  pub fn checked_sub(&self, other: &Matrix<T>) -> Result<Matrix<T>, MatrixError> {
+    self.zip_with("subtract", other, |a, b| a - b)
+  }
+
+  /// The matrix product `self * other`, which needs as many columns in
+  /// `self` as there are rows in `other`.
+  pub fn checked_mul(&self, other: &Matrix<T>) -> Result<Matrix<T>, MatrixError> {
+    if self.cols != other.rows {
+      return Err(self.mismatch("multiply", other));
+    }
+    let mut product = Matrix::filled(self.rows, other.cols, T::ZERO);
+    // Row by row through both operands, so every inner loop runs over
+    // contiguous memory.
+    for row in 0..self.rows {
+      let out = product.row_mut(row);
+      for (inner, &a) in self.row(row).iter().enumerate() {
+        for (out, &b) in out.iter_mut().zip(other.row(inner)) {
+          *out = *out + a * b;
+        }
+      }
+    }
+    Ok(product)
+  }
+
+  /// Every element multiplied by `factor`.
+  pub fn scale(&self, factor: T) -> Matrix<T> {
+    self.map(|&element| element * factor)
+  }
+
+  fn zip_with(
+    &self,
+    operation: &'static str,
+    other: &Matrix<T>,
+    f: impl Fn(T, T) -> T,
+  ) -> Result<Matrix<T>, MatrixError> {
+    if self.shape() != other.shape() {
+      return Err(self.mismatch(operation, other));
+    }
+    let data = self
+      .data
+      .iter()
+      .zip(&other.data)
+      .map(|(&a, &b)| f(a, b))
+      .collect();
+    Ok(Matrix {
+      rows: self.rows,
+      cols: self.cols,
+      data,
+    })
+  }
+}
+
+impl<T> Matrix<T> {
+  fn mismatch<U>(&self, operation: &'static str, other: &Matrix<U>) -> MatrixError {
+    MatrixError::DimensionMismatch {
+      operation,
+      left: self.shape(),
+      right: other.shape(),
+    }
+  }
+}
+
+fn expect<T>(result: Result<Matrix<T>, MatrixError>) -> Matrix<T> {
+  result.unwrap_or_else(|error| panic!("{error}"))
+}
+
+impl<T: Scalar> Add for &Matrix<T> {
+  type Output = Matrix<T>;
+
+  fn add(self, other: &Matrix<T>) -> Matrix<T> {
+    expect(self.checked_add(other))
+  }
+}
+
+impl<T: Scalar> Add for Matrix<T> {
+  type Output = Matrix<T>;
+
+  fn add(mut self, other: Matrix<T>) -> Matrix<T> {
+    self += &other;
+    self
+  }
+}
+
+impl<T: Scalar> AddAssign<&Matrix<T>> for Matrix<T> {
+  fn add_assign(&mut self, other: &Matrix<T>) {
+    if self.shape() != other.shape() {
+      panic!("{}", self.mismatch("add", other));
+    }
+    for (a, &b) in self.data.iter_mut().zip(&other.data) {
+      *a = *a + b;
+    }
+  }
+}
+
+impl<T: Scalar> Sub for &Matrix<T> {
+  type Output = Matrix<T>;
+
+  fn sub(self, other: &Matrix<T>) -> Matrix<T> {
+    expect(self.checked_sub(other))
+  }
+}
+
+impl<T: Scalar> Sub for Matrix<T> {
+  type Output = Matrix<T>;
+
+  fn sub(mut self, other: Matrix<T>) -> Matrix<T> {
+    self -= &other;
+    self
+  }
+}
+
+impl<T: Scalar> SubAssign<&Matrix<T>> for Matrix<T> {
+  fn sub_assign(&mut self, other: &Matrix<T>) {
+    if self.shape() != other.shape() {
+      panic!("{}", self.mismatch("subtract", other));
+    }
+    for (a, &b) in self.data.iter_mut().zip(&other.data) {
+      *a = *a - b;
+    }
+  }
+}
+
+impl<T: Scalar + Neg<Output = T>> Neg for &Matrix<T> {
+  type Output = Matrix<T>;
+
+  fn neg(self) -> Matrix<T> {
+    self.map(|&element| -element)
+  }
+}
+
+impl<T: Scalar> Mul for &Matrix<T> {
+  type Output = Matrix<T>;
+
+  fn mul(self, other: &Matrix<T>) -> Matrix<T> {
+    expect(self.checked_mul(other))
+  }
+}
+
+impl<T: Scalar> Mul for Matrix<T> {
+  type Output = Matrix<T>;
+
+  fn mul(self, other: Matrix<T>) -> Matrix<T> {
+    &self * &other
+  }
+}
+
+impl<T: Scalar> Mul<T> for &Matrix<T> {
+  type Output = Matrix<T>;
+
+  fn mul(self, factor: T) -> Matrix<T> {
+    self.scale(factor)
+  }
+}
+
+impl<T: Scalar> Mul<T> for Matrix<T> {
+  type Output = Matrix<T>;
+
+  fn mul(mut self, factor: T) -> Matrix<T> {
+    for element in &mut self.data {
+      *element = *element * factor;
+    }
+    self
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use proptest::prelude::*;
+
+  use super::*;
+
+  /// A `rows` x `cols` matrix of small integers, so products stay exact.
+  fn matrix(rows: usize, cols: usize) -> impl Strategy<Value = Matrix<i64>> {
+    prop::collection::vec(-50i64..50, rows * cols)
+      .prop_map(move |data| Matrix::from_vec(rows, cols, data).unwrap())
+  }
+
+  /// Matrices shaped `n` x `m`, `m` x `p` and `p` x `q`, so each can be
+  /// multiplied by the next.
+  fn chain() -> impl Strategy<Value = (Matrix<i64>, Matrix<i64>, Matrix<i64>)> {
+    (1..6usize, 1..6usize, 1..6usize, 1..6usize)
+      .prop_flat_map(|(n, m, p, q)| (matrix(n, m), matrix(m, p), matrix(p, q)))
+  }
+
+  #[test]
+  fn test_element_wise_and_scalar() {
+    let a = Matrix::from([[1, 2, 3], [4, 5, 6]]);
+    let b = Matrix::from([[6, 5, 4], [3, 2, 1]]);
+
+    assert_eq!(&a + &b, Matrix::filled(2, 3, 7));
+    assert_eq!(&a - &a, Matrix::filled(2, 3, 0));
+    assert_eq!(a.clone() * 2, Matrix::from([[2, 4, 6], [8, 10, 12]]));
+    assert_eq!(-&a, a.scale(-1));
+    let mut sum = a.clone();
+    sum += &b;
+    sum -= &b;
+    assert_eq!(sum, a);
+  }
+
+  #[test]
+  fn test_matrix_product() {
+    let a = Matrix::from([[1, 2, 3], [4, 5, 6]]);
+    let b = Matrix::from([[7, 8], [9, 10], [11, 12]]);
+
+    assert_eq!(&a * &b, Matrix::from([[58, 64], [139, 154]]));
+    assert_eq!(&Matrix::identity(2) * &a, a);
+    assert_eq!(&a * &Matrix::identity(3), a);
+  }
+
+  #[test]
+  fn test_shape_mismatches() {
+    let a = Matrix::from([[1, 2, 3], [4, 5, 6]]);
+    let error = a.checked_mul(&a).unwrap_err();
+
+    assert_eq!(
+      error,
+      MatrixError::DimensionMismatch {
+        operation: "multiply",
+        left: (2, 3),
+        right: (2, 3),
+      }
+    );
+    assert_eq!(error.to_string(), "cannot multiply a 2x3 and a 2x3 matrix");
+    assert!(a.checked_add(&a.transpose()).is_err());
+    assert!(a.checked_sub(&a.transpose()).is_err());
+  }
+
+  #[test]
+  #[should_panic(expected = "cannot add a 2x3 and a 3x2 matrix")]
+  fn test_operator_panics_on_mismatch() {
+    let a = Matrix::from([[1, 2, 3], [4, 5, 6]]);
+    let _ = &a + &a.transpose();
+  }
+
+  proptest! {
+    #[test]
+    fn test_transpose_of_product((a, b, _) in chain()) {
+      prop_assert_eq!((&a * &b).transpose(), &b.transpose() * &a.transpose());
+    }
+
+    #[test]
+    fn test_product_is_associative((a, b, c) in chain()) {
+      prop_assert_eq!(&(&a * &b) * &c, &a * &(&b * &c));
+    }
+
+    #[test]
+    fn test_product_distributes_over_sum(
+      (a, b, c) in (1..6usize, 1..6usize, 1..6usize)
+        .prop_flat_map(|(n, m, p)| (matrix(n, m), matrix(m, p), matrix(m, p)))
+    ) {
+      prop_assert_eq!(&a * &(&b + &c), &(&a * &b) + &(&a * &c));
+      prop_assert_eq!(&(&a + &a) - &a, a);
+    }
+  }
+}
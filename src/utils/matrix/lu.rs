@@ -0,0 +1,252 @@
+// LU decomposition
+// Gaussian elimination with partial pivoting, kept as the factors of
+// PA = LU so one elimination gives the determinant, the inverse and the
+// solution of Ax = b for any number of right-hand sides. A pivot within
+// rounding error of zero, relative to the largest element, makes the matrix
+// singular: its determinant is 0, and it has no inverse or unique solution.
+use super::{Matrix, MatrixError};
+
+/// The factors of a square matrix A, with PA = LU.
+#[derive(Debug, Clone)]
+pub struct Lu {
+  /// L below the diagonal, whose own diagonal is all ones, and U on and
+  /// above it.
+  factors: Matrix<f64>,
+  /// Row `i` of PA is row `permutation[i]` of A.
+  permutation: Vec<usize>,
+  /// Whether P swaps an odd number of rows, which flips the determinant.
+  odd_swaps: bool,
+  singular: bool,
+}
+
+impl Lu {
+  pub fn new(matrix: &Matrix<f64>) -> Result<Lu, MatrixError> {
+    if !matrix.is_square() {
+      let (rows, cols) = matrix.shape();
+      return Err(MatrixError::NotSquare { rows, cols });
+    }
+    let size = matrix.rows();
+    let largest = matrix
+      .as_slice()
+      .iter()
+      .fold(0.0, |max: f64, x| max.max(x.abs()));
+    let tolerance = largest * size as f64 * f64::EPSILON;
+    let mut factors = matrix.clone();
+    let mut permutation: Vec<usize> = (0..size).collect();
+    let mut odd_swaps = false;
+    let mut singular = false;
+    for col in 0..size {
+      let pivot = (col..size)
+        .max_by(|&a, &b| factors[(a, col)].abs().total_cmp(&factors[(b, col)].abs()))
+        .unwrap_or(col);
+      if factors[(pivot, col)].abs() <= tolerance {
+        // Nothing left to eliminate with: the column is already zero below
+        // the diagonal, as far as rounding can tell.
+        singular = true;
+        for row in col + 1..size {
+          factors[(row, col)] = 0.0;
+        }
+        continue;
+      }
+      if pivot != col {
+        factors.swap_rows(pivot, col);
+        permutation.swap(pivot, col);
+        odd_swaps = !odd_swaps;
+      }
+      for row in col + 1..size {
+        let factor = factors[(row, col)] / factors[(col, col)];
+        factors[(row, col)] = factor;
+        for inner in col + 1..size {
+          let above = factors[(col, inner)];
+          factors[(row, inner)] -= factor * above;
+        }
+      }
+    }
+    Ok(Lu {
+      factors,
+      permutation,
+      odd_swaps,
+      singular,
+    })
+  }
+
+  pub fn is_singular(&self) -> bool {
+    self.singular
+  }
+
+  pub fn determinant(&self) -> f64 {
+    if self.singular {
+      return 0.0;
+    }
+    let size = self.factors.rows();
+    let product: f64 = (0..size).map(|i| self.factors[(i, i)]).product();
+    if self.odd_swaps {
+      -product
+    } else {
+      product
+    }
+  }
+
+  /// The x with Ax = b.
+  pub fn solve(&self, b: &[f64]) -> Result<Vec<f64>, MatrixError> {
+    let size = self.factors.rows();
+    if b.len() != size {
+      return Err(MatrixError::DimensionMismatch {
+        operation: "solve",
+        left: self.factors.shape(),
+        right: (b.len(), 1),
+      });
+    }
+    if self.singular {
+      return Err(MatrixError::Singular);
+    }
+    // Ly = Pb, top down, then Ux = y, bottom up.
+    let mut x: Vec<f64> = self.permutation.iter().map(|&row| b[row]).collect();
+    for row in 0..size {
+      let lower = &self.factors.row(row)[..row];
+      x[row] -= dot(lower, &x[..row]);
+    }
+    for row in (0..size).rev() {
+      let upper = &self.factors.row(row)[row + 1..];
+      x[row] = (x[row] - dot(upper, &x[row + 1..])) / self.factors[(row, row)];
+    }
+    Ok(x)
+  }
+
+  pub fn inverse(&self) -> Result<Matrix<f64>, MatrixError> {
+    let size = self.factors.rows();
+    let identity = Matrix::<f64>::identity(size);
+    let columns = identity
+      .row_iter()
+      .map(|unit| self.solve(unit))
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(Matrix::from_fn(size, size, |row, col| columns[col][row]))
+  }
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+  a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+impl Matrix<f64> {
+  pub fn lu(&self) -> Result<Lu, MatrixError> {
+    Lu::new(self)
+  }
+
+  pub fn determinant(&self) -> Result<f64, MatrixError> {
+    Ok(self.lu()?.determinant())
+  }
+
+  pub fn inverse(&self) -> Result<Matrix<f64>, MatrixError> {
+    self.lu()?.inverse()
+  }
+
+  /// The x with `self` x = b, by Gaussian elimination. To solve for several
+  /// b, factor once with `lu` and call `Lu::solve` for each.
+  pub fn solve(&self, b: &[f64]) -> Result<Vec<f64>, MatrixError> {
+    self.lu()?.solve(b)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use proptest::prelude::*;
+
+  use super::*;
+
+  fn assert_close(actual: &Matrix<f64>, expected: &Matrix<f64>) {
+    assert_eq!(actual.shape(), expected.shape());
+    for (a, e) in actual.as_slice().iter().zip(expected.as_slice()) {
+      assert!(
+        (a - e).abs() < 1e-9,
+        "{actual}\nis not close to\n{expected}"
+      );
+    }
+  }
+
+  /// A strictly diagonally dominant matrix, which is never singular.
+  fn invertible() -> impl Strategy<Value = Matrix<f64>> {
+    (1..8usize).prop_flat_map(|size| {
+      prop::collection::vec(-10.0f64..10.0, size * size).prop_map(move |data| {
+        let mut matrix = Matrix::from_vec(size, size, data).unwrap();
+        for i in 0..size {
+          matrix[(i, i)] += 10.0 * size as f64 * matrix[(i, i)].signum();
+        }
+        matrix
+      })
+    })
+  }
+
+  #[test]
+  fn test_determinant() {
+    let matrix = Matrix::from([[0.0, 2.0, 1.0], [1.0, 1.0, 0.0], [2.0, 1.0, 3.0]]);
+    assert!((matrix.determinant().unwrap() - -7.0).abs() < 1e-12);
+    assert_eq!(Matrix::<f64>::identity(4).determinant(), Ok(1.0));
+    assert_eq!(Matrix::<f64>::identity(0).determinant(), Ok(1.0));
+  }
+
+  #[test]
+  fn test_inverse_and_solve() {
+    let matrix = Matrix::from([[4.0, 7.0], [2.0, 6.0]]);
+    assert_close(
+      &matrix.inverse().unwrap(),
+      &Matrix::from([[0.6, -0.7], [-0.2, 0.4]]),
+    );
+
+    // 2x + y - z = 8, -3x - y + 2z = -11, -2x + y + 2z = -3
+    let system = Matrix::from([[2.0, 1.0, -1.0], [-3.0, -1.0, 2.0], [-2.0, 1.0, 2.0]]);
+    let x = system.solve(&[8.0, -11.0, -3.0]).unwrap();
+    for (x, expected) in x.iter().zip([2.0, 3.0, -1.0]) {
+      assert!((x - expected).abs() < 1e-12, "{x} != {expected}");
+    }
+  }
+
+  #[test]
+  fn test_singular_and_misshapen_matrices() {
+    let singular = Matrix::from([[1.0, 2.0], [2.0, 4.0]]);
+    assert_eq!(singular.determinant(), Ok(0.0));
+    assert_eq!(singular.inverse(), Err(MatrixError::Singular));
+    assert_eq!(singular.solve(&[1.0, 2.0]), Err(MatrixError::Singular));
+    assert!(singular.lu().unwrap().is_singular());
+
+    let wide = Matrix::from([[1.0, 2.0, 3.0]]);
+    assert_eq!(
+      wide.determinant(),
+      Err(MatrixError::NotSquare { rows: 1, cols: 3 })
+    );
+    assert!(matches!(
+      Matrix::<f64>::identity(2).solve(&[1.0]),
+      Err(MatrixError::DimensionMismatch { .. })
+    ));
+  }
+
+  proptest! {
+    #[test]
+    fn test_inverse_is_two_sided(matrix in invertible()) {
+      let inverse = matrix.inverse().unwrap();
+      let identity = Matrix::identity(matrix.rows());
+      assert_close(&(&matrix * &inverse), &identity);
+      assert_close(&(&inverse * &matrix), &identity);
+    }
+
+    #[test]
+    fn test_solution_satisfies_the_system(matrix in invertible()) {
+      let b: Vec<f64> = (0..matrix.rows()).map(|i| i as f64 - 2.0).collect();
+      let x = matrix.solve(&b).unwrap();
+      let column = Matrix::from_vec(x.len(), 1, x).unwrap();
+      let expected = Matrix::from_vec(b.len(), 1, b).unwrap();
+      assert_close(&(&matrix * &column), &expected);
+    }
+
+    #[test]
+    fn test_determinant_is_multiplicative(a in invertible(), seed in invertible()) {
+      let size = a.rows();
+      let b = Matrix::from_fn(size, size, |row, col| {
+        seed.get(row, col).copied().unwrap_or(if row == col { 1.0 } else { 0.0 })
+      });
+      let product = (&a * &b).determinant().unwrap();
+      let expected = a.determinant().unwrap() * b.determinant().unwrap();
+      prop_assert!((product - expected).abs() <= 1e-9 * expected.abs().max(1.0));
+    }
+  }
+}
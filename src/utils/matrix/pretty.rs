@@ -0,0 +1,371 @@
+// Pretty printing
+// Lays a matrix out with every column right-aligned, either one bracketed
+// row per line or between Unicode brackets:
+//
+//   [  1, 20, 3]      ⎡   1  20  3 ⎤
+//   [400,  5, 6]      ⎣ 400   5  6 ⎦
+//
+// Matrices with more rows or columns than fit are cut in the middle, with
+// `...` standing for what is left out. The same settings export the whole
+// matrix as a Markdown table, CSV or a LaTeX `bmatrix`.
+use std::borrow::Cow;
+use std::fmt;
+use std::slice;
+
+use super::Matrix;
+
+/// Anything laid out in rows and columns that can be printed, dense or not.
+pub trait Grid {
+  type Element: fmt::Display + Clone;
+
+  fn shape(&self) -> (usize, usize);
+
+  /// The element at (row, col), which is always in bounds.
+  fn element(&self, row: usize, col: usize) -> Cow<'_, Self::Element>;
+}
+
+impl<T: fmt::Display + Clone> Grid for Matrix<T> {
+  type Element = T;
+
+  fn shape(&self) -> (usize, usize) {
+    Matrix::shape(self)
+  }
+
+  fn element(&self, row: usize, col: usize) -> Cow<'_, T> {
+    Cow::Borrowed(&self[(row, col)])
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Brackets {
+  /// `[a, b]` around every row, as `Debug` prints a slice.
+  #[default]
+  Rows,
+  /// `⎡ ⎤`, `⎢ ⎥` and `⎣ ⎦` around the whole matrix, as it is written by
+  /// hand.
+  Unicode,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrettyPrinter {
+  /// Passed on to every element as `{:.precision$}`: the digits after the
+  /// decimal point of floats. Integers ignore it, but strings are cut to that
+  /// many characters.
+  pub precision: Option<usize>,
+  /// How many rows to show before eliding the middle ones.
+  pub max_rows: usize,
+  /// How many columns to show before eliding the middle ones.
+  pub max_cols: usize,
+  pub brackets: Brackets,
+}
+
+impl Default for PrettyPrinter {
+  fn default() -> PrettyPrinter {
+    PrettyPrinter {
+      precision: None,
+      max_rows: 20,
+      max_cols: 12,
+      brackets: Brackets::default(),
+    }
+  }
+}
+
+const ELLIPSIS: &str = "...";
+
+impl PrettyPrinter {
+  /// The matrix laid out for a terminal, one line per row.
+  pub fn render(&self, matrix: &impl Grid) -> String {
+    let (rows, cols) = matrix.shape();
+    let rows = shown(rows, self.max_rows);
+    let cols = shown(cols, self.max_cols);
+    let cells: Vec<Vec<String>> = rows
+      .iter()
+      .map(|row| {
+        cols
+          .iter()
+          .map(|col| match (row, col) {
+            (Some(row), Some(col)) => self.cell(matrix, *row, *col),
+            _ => String::from(ELLIPSIS),
+          })
+          .collect()
+      })
+      .collect();
+    let widths = widths(&cells, cols.len());
+
+    let mut out = String::new();
+    if cells.is_empty() && self.brackets == Brackets::Unicode {
+      out.push_str("[]\n");
+    }
+    for (index, row) in cells.iter().enumerate() {
+      let aligned: Vec<String> = row
+        .iter()
+        .zip(&widths)
+        .map(|(cell, &width)| format!("{cell:>width$}"))
+        .collect();
+      let line = match self.brackets {
+        Brackets::Rows => format!("[{}]", aligned.join(", ")),
+        Brackets::Unicode => {
+          let (open, close) = match (index, cells.len() - 1) {
+            (_, 0) => ('[', ']'),
+            (0, _) => ('⎡', '⎤'),
+            (index, last) if index == last => ('⎣', '⎦'),
+            _ => ('⎢', '⎥'),
+          };
+          format!("{open} {} {close}", aligned.join("  "))
+        }
+      };
+      out.push_str(&line);
+      out.push('\n');
+    }
+    out
+  }
+
+  /// A Markdown table of every element, headed by the column numbers.
+  pub fn to_markdown(&self, matrix: &impl Grid) -> String {
+    let cells = escape_all(self.all_cells(matrix), markdown_escape);
+    let (_, cols) = matrix.shape();
+    let header: Vec<String> = (0..cols).map(|col| col.to_string()).collect();
+    let widths = widths(slice::from_ref(&header), cols)
+      .into_iter()
+      .zip(widths(&cells, cols))
+      .map(|(header, cells)| header.max(cells).max(2))
+      .collect::<Vec<_>>();
+
+    let line = |row: &[String]| {
+      let aligned: Vec<String> = row
+        .iter()
+        .zip(&widths)
+        .map(|(cell, &width)| format!(" {cell:>width$} "))
+        .collect();
+      format!("|{}|\n", aligned.join("|"))
+    };
+    let mut out = line(&header);
+    let rule: Vec<String> = widths
+      .iter()
+      .map(|&width| format!(" {}: ", "-".repeat(width - 1)))
+      .collect();
+    out.push_str(&format!("|{}|\n", rule.join("|")));
+    for row in &cells {
+      out.push_str(&line(row));
+    }
+    out
+  }
+
+  /// Every element as comma-separated values, one line per row.
+  pub fn to_csv(&self, matrix: &impl Grid) -> String {
+    self
+      .all_cells(matrix)
+      .iter()
+      .map(|row| {
+        let fields: Vec<Cow<str>> = row.iter().map(|cell| csv_field(cell)).collect();
+        fields.join(",") + "\n"
+      })
+      .collect()
+  }
+
+  /// Every element in a LaTeX `bmatrix` environment.
+  pub fn to_latex(&self, matrix: &impl Grid) -> String {
+    let rows: Vec<String> = escape_all(self.all_cells(matrix), latex_escape)
+      .iter()
+      .map(|row| format!("  {}", row.join(" & ")))
+      .collect();
+    format!(
+      "\\begin{{bmatrix}}\n{}\n\\end{{bmatrix}}\n",
+      rows.join(" \\\\\n")
+    )
+  }
+
+  fn cell(&self, matrix: &impl Grid, row: usize, col: usize) -> String {
+    let element = matrix.element(row, col);
+    match self.precision {
+      Some(precision) => format!("{:.precision$}", element),
+      None => element.to_string(),
+    }
+  }
+
+  fn all_cells(&self, matrix: &impl Grid) -> Vec<Vec<String>> {
+    let (rows, cols) = matrix.shape();
+    (0..rows)
+      .map(|row| (0..cols).map(|col| self.cell(matrix, row, col)).collect())
+      .collect()
+  }
+}
+
+/// The indices to show out of `len`, with `None` where the ones left out
+/// go.
+fn shown(len: usize, max: usize) -> Vec<Option<usize>> {
+  if len <= max {
+    return (0..len).map(Some).collect();
+  }
+  let head = max.div_ceil(2);
+  let tail = max / 2;
+  (0..head)
+    .map(Some)
+    .chain([None])
+    .chain((len - tail..len).map(Some))
+    .collect()
+}
+
+/// The widest cell in each column.
+fn widths(cells: &[Vec<String>], cols: usize) -> Vec<usize> {
+  (0..cols)
+    .map(|col| {
+      cells
+        .iter()
+        .map(|row| row[col].chars().count())
+        .max()
+        .unwrap_or(0)
+    })
+    .collect()
+}
+
+fn escape_all(cells: Vec<Vec<String>>, escape: fn(&str) -> String) -> Vec<Vec<String>> {
+  cells
+    .into_iter()
+    .map(|row| row.iter().map(|cell| escape(cell)).collect())
+    .collect()
+}
+
+/// A cell that cannot end the table cell early or start an escape.
+fn markdown_escape(cell: &str) -> String {
+  cell.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// A cell with LaTeX's special characters written out as text.
+fn latex_escape(cell: &str) -> String {
+  let mut escaped = String::with_capacity(cell.len());
+  for c in cell.chars() {
+    match c {
+      '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+        escaped.push('\\');
+        escaped.push(c);
+      }
+      '\\' => escaped.push_str("\\textbackslash{}"),
+      '~' => escaped.push_str("\\textasciitilde{}"),
+      '^' => escaped.push_str("\\textasciicircum{}"),
+      _ => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+fn csv_field(cell: &str) -> Cow<'_, str> {
+  if cell.contains([',', '"', '\n']) {
+    Cow::Owned(format!("\"{}\"", cell.replace('"', "\"\"")))
+  } else {
+    Cow::Borrowed(cell)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn matrix() -> Matrix<i32> {
+    Matrix::from([[1, 20, 3], [400, 5, 6]])
+  }
+
+  #[test]
+  fn test_columns_are_right_aligned() {
+    let rows = PrettyPrinter::default();
+    let unicode = PrettyPrinter {
+      brackets: Brackets::Unicode,
+      ..PrettyPrinter::default()
+    };
+
+    assert_eq!(rows.render(&matrix()), "[  1, 20, 3]\n[400,  5, 6]\n");
+    assert_eq!(
+      unicode.render(&Matrix::from([[1, 20], [300, 4], [5, 6]])),
+      "⎡   1  20 ⎤\n⎢ 300   4 ⎥\n⎣   5   6 ⎦\n"
+    );
+    assert_eq!(unicode.render(&Matrix::from([[1, 2]])), "[ 1  2 ]\n");
+    assert_eq!(
+      unicode.render(&Matrix::<i32>::from_rows::<[i32; 0]>(&[]).unwrap()),
+      "[]\n"
+    );
+  }
+
+  #[test]
+  fn test_precision_applies_to_floats() {
+    let printer = PrettyPrinter {
+      precision: Some(2),
+      ..PrettyPrinter::default()
+    };
+
+    assert_eq!(
+      printer.render(&Matrix::from([[1.0, -0.5], [10.0 / 3.0, 100.0]])),
+      "[1.00,  -0.50]\n[3.33, 100.00]\n"
+    );
+    assert_eq!(printer.render(&matrix()), "[  1, 20, 3]\n[400,  5, 6]\n");
+  }
+
+  #[test]
+  fn test_large_matrices_are_elided() {
+    let printer = PrettyPrinter {
+      max_rows: 3,
+      max_cols: 2,
+      ..PrettyPrinter::default()
+    };
+    let matrix = Matrix::from_fn(5, 5, |row, col| row * 10 + col);
+
+    assert_eq!(
+      printer.render(&matrix),
+      "[  0, ...,   4]\n\
+       [ 10, ...,  14]\n\
+       [..., ..., ...]\n\
+       [ 40, ...,  44]\n"
+    );
+  }
+
+  #[test]
+  fn test_exports() {
+    let printer = PrettyPrinter {
+      max_rows: 1,
+      ..PrettyPrinter::default()
+    };
+
+    assert_eq!(
+      printer.to_markdown(&matrix()),
+      "|   0 |  1 |  2 |\n\
+       | --: | -: | -: |\n\
+       |   1 | 20 |  3 |\n\
+       | 400 |  5 |  6 |\n"
+    );
+    assert_eq!(printer.to_csv(&matrix()), "1,20,3\n400,5,6\n");
+    assert_eq!(
+      printer.to_csv(&Matrix::from([["a,b", "say \"hi\""]])),
+      "\"a,b\",\"say \"\"hi\"\"\"\n"
+    );
+    assert_eq!(
+      printer.to_latex(&matrix()),
+      "\\begin{bmatrix}\n  1 & 20 & 3 \\\\\n  400 & 5 & 6\n\\end{bmatrix}\n"
+    );
+  }
+
+  #[test]
+  fn test_exports_escape_special_characters() {
+    let printer = PrettyPrinter::default();
+    let matrix = Matrix::from([["a|b", "50%"], ["x & y", "C:\\dir_1"]]);
+
+    assert_eq!(
+      printer.to_markdown(&matrix),
+      "|     0 |         1 |\n\
+       | ----: | --------: |\n\
+       |  a\\|b |       50% |\n\
+       | x & y | C:\\\\dir_1 |\n"
+    );
+    assert_eq!(
+      printer.to_latex(&matrix),
+      "\\begin{bmatrix}\n  a|b & 50\\% \\\\\n  x \\& y & C:\\textbackslash{}dir\\_1\n\\end{bmatrix}\n"
+    );
+  }
+
+  #[test]
+  fn test_precision_cuts_strings() {
+    let printer = PrettyPrinter {
+      precision: Some(2),
+      ..PrettyPrinter::default()
+    };
+    assert_eq!(printer.render(&Matrix::from([["abc", "d"]])), "[ab, d]\n");
+  }
+}
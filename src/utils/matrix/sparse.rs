@@ -0,0 +1,552 @@
+// Sparse matrices
+// Matrices that are mostly zeros, like adjacency matrices, storing only the
+// other elements:
+//
+// - `CooMatrix` is a list of (row, col, value) entries, the easy way to
+//   build one up in any order.
+// - `CsrMatrix` keeps the entries row by row and `CscMatrix` column by
+//   column, each as one offset per row (or column) into the column (or row)
+//   indices and values of its entries.
+//
+// Transposing a CSR matrix is turning its rows into columns, which is
+// exactly what converting it to CSC does; read as CSR again, the result is
+// the transpose. That takes time in the number of entries, not of elements.
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Mul;
+
+use super::pretty::{Grid, PrettyPrinter};
+use super::{Matrix, MatrixError, Scalar};
+
+/// Entries in any order. Entries at the same position add up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CooMatrix<T> {
+  rows: usize,
+  cols: usize,
+  entries: Vec<(usize, usize, T)>,
+}
+
+/// The entries of a CSR matrix's rows or a CSC matrix's columns, which
+/// this calls lines.
+#[derive(Debug, Clone, PartialEq)]
+struct Compressed<T> {
+  /// Line `i` holds the entries at `offsets[i]..offsets[i + 1]`.
+  offsets: Vec<usize>,
+  /// Where each entry is within its line, ascending in every line.
+  indices: Vec<usize>,
+  values: Vec<T>,
+}
+
+/// Compressed sparse rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsrMatrix<T> {
+  rows: usize,
+  cols: usize,
+  storage: Compressed<T>,
+}
+
+/// Compressed sparse columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CscMatrix<T> {
+  rows: usize,
+  cols: usize,
+  storage: Compressed<T>,
+}
+
+impl<T: Scalar> CooMatrix<T> {
+  /// A `rows` x `cols` matrix of zeros.
+  pub fn new(rows: usize, cols: usize) -> CooMatrix<T> {
+    CooMatrix {
+      rows,
+      cols,
+      entries: Vec::new(),
+    }
+  }
+
+  /// Add `value` at (row, col).
+  pub fn push(&mut self, row: usize, col: usize, value: T) -> Result<(), MatrixError> {
+    if row >= self.rows || col >= self.cols {
+      return Err(MatrixError::OutOfBounds {
+        row,
+        col,
+        rows: self.rows,
+        cols: self.cols,
+      });
+    }
+    self.entries.push((row, col, value));
+    Ok(())
+  }
+
+  pub fn shape(&self) -> (usize, usize) {
+    (self.rows, self.cols)
+  }
+
+  /// How many entries were pushed, counting repeated positions each time.
+  pub fn entry_count(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn to_csr(&self) -> CsrMatrix<T> {
+    CsrMatrix {
+      rows: self.rows,
+      cols: self.cols,
+      storage: Compressed::from_entries(self.rows, self.entries.iter().copied()),
+    }
+  }
+
+  pub fn to_csc(&self) -> CscMatrix<T> {
+    let entries = self
+      .entries
+      .iter()
+      .map(|&(row, col, value)| (col, row, value));
+    CscMatrix {
+      rows: self.rows,
+      cols: self.cols,
+      storage: Compressed::from_entries(self.cols, entries),
+    }
+  }
+
+  pub fn to_dense(&self) -> Matrix<T> {
+    let mut dense = Matrix::filled(self.rows, self.cols, T::ZERO);
+    for &(row, col, value) in &self.entries {
+      dense[(row, col)] = dense[(row, col)] + value;
+    }
+    dense
+  }
+}
+
+impl<T: Scalar> Compressed<T> {
+  /// From (line, index, value) entries in any order.
+  fn from_entries(lines: usize, entries: impl Iterator<Item = (usize, usize, T)>) -> Compressed<T> {
+    let mut entries: Vec<_> = entries.collect();
+    entries.sort_by_key(|&(line, index, _)| (line, index));
+    let mut offsets = vec![0; lines + 1];
+    let mut indices: Vec<usize> = Vec::with_capacity(entries.len());
+    let mut values: Vec<T> = Vec::with_capacity(entries.len());
+    let mut previous = None;
+    for (line, index, value) in entries {
+      if previous == Some((line, index)) {
+        let last = values.len() - 1;
+        values[last] = values[last] + value;
+        continue;
+      }
+      previous = Some((line, index));
+      offsets[line + 1] += 1;
+      indices.push(index);
+      values.push(value);
+    }
+    for line in 0..lines {
+      offsets[line + 1] += offsets[line];
+    }
+    Compressed {
+      offsets,
+      indices,
+      values,
+    }
+  }
+
+  /// The non-zero elements of a dense matrix, row by row.
+  fn from_dense(dense: &Matrix<T>) -> Compressed<T> {
+    let mut offsets = Vec::with_capacity(dense.rows() + 1);
+    let mut indices = Vec::new();
+    let mut values = Vec::new();
+    offsets.push(0);
+    for row in dense.row_iter() {
+      for (col, &value) in row.iter().enumerate() {
+        if value != T::ZERO {
+          indices.push(col);
+          values.push(value);
+        }
+      }
+      offsets.push(values.len());
+    }
+    Compressed {
+      offsets,
+      indices,
+      values,
+    }
+  }
+
+  /// The same entries with lines and indices swapped, for a matrix with
+  /// `len` elements per line. Counts the entries at each index first to
+  /// place every entry directly; walking the lines in order keeps each new
+  /// line ascending.
+  fn transpose(&self, len: usize) -> Compressed<T> {
+    let mut offsets = vec![0; len + 1];
+    for &index in &self.indices {
+      offsets[index + 1] += 1;
+    }
+    for index in 0..len {
+      offsets[index + 1] += offsets[index];
+    }
+    let mut next = offsets.clone();
+    let mut indices = vec![0; self.values.len()];
+    let mut values = vec![T::ZERO; self.values.len()];
+    for line in 0..self.offsets.len() - 1 {
+      for (index, &value) in self.line(line) {
+        indices[next[index]] = line;
+        values[next[index]] = value;
+        next[index] += 1;
+      }
+    }
+    Compressed {
+      offsets,
+      indices,
+      values,
+    }
+  }
+
+  fn line(&self, line: usize) -> impl Iterator<Item = (usize, &T)> {
+    let range = self.offsets[line]..self.offsets[line + 1];
+    self.indices[range.clone()]
+      .iter()
+      .copied()
+      .zip(&self.values[range])
+  }
+
+  fn get(&self, line: usize, index: usize) -> Option<&T> {
+    let range = self.offsets[line]..self.offsets[line + 1];
+    let found = self.indices[range.clone()].binary_search(&index).ok()?;
+    Some(&self.values[range.start + found])
+  }
+
+  fn nnz(&self) -> usize {
+    self.values.len()
+  }
+}
+
+impl<T: Scalar> CsrMatrix<T> {
+  pub fn from_dense(dense: &Matrix<T>) -> CsrMatrix<T> {
+    CsrMatrix {
+      rows: dense.rows(),
+      cols: dense.cols(),
+      storage: Compressed::from_dense(dense),
+    }
+  }
+
+  pub fn to_dense(&self) -> Matrix<T> {
+    let mut dense = Matrix::filled(self.rows, self.cols, T::ZERO);
+    for row in 0..self.rows {
+      for (col, &value) in self.storage.line(row) {
+        dense[(row, col)] = value;
+      }
+    }
+    dense
+  }
+
+  pub fn shape(&self) -> (usize, usize) {
+    (self.rows, self.cols)
+  }
+
+  /// How many elements are stored.
+  pub fn nnz(&self) -> usize {
+    self.storage.nnz()
+  }
+
+  /// The element at (row, col), or `None` if it is out of bounds.
+  pub fn get(&self, row: usize, col: usize) -> Option<T> {
+    if row >= self.rows || col >= self.cols {
+      return None;
+    }
+    Some(self.storage.get(row, col).copied().unwrap_or(T::ZERO))
+  }
+
+  /// The stored elements of row `row` as (column, value), left to right.
+  pub fn row(&self, row: usize) -> impl Iterator<Item = (usize, &T)> {
+    self.storage.line(row)
+  }
+
+  /// The same matrix, stored by columns.
+  pub fn to_csc(&self) -> CscMatrix<T> {
+    CscMatrix {
+      rows: self.rows,
+      cols: self.cols,
+      storage: self.storage.transpose(self.cols),
+    }
+  }
+
+  /// The `cols` x `rows` matrix with rows and columns swapped.
+  pub fn transpose(&self) -> CsrMatrix<T> {
+    CsrMatrix {
+      rows: self.cols,
+      cols: self.rows,
+      storage: self.storage.transpose(self.cols),
+    }
+  }
+
+  /// The product `self * other` with a dense matrix.
+  pub fn checked_mul(&self, other: &Matrix<T>) -> Result<Matrix<T>, MatrixError> {
+    if self.cols != other.rows() {
+      return Err(MatrixError::DimensionMismatch {
+        operation: "multiply",
+        left: self.shape(),
+        right: other.shape(),
+      });
+    }
+    let mut product = Matrix::filled(self.rows, other.cols(), T::ZERO);
+    for row in 0..self.rows {
+      let out = product.row_mut(row);
+      for (inner, &a) in self.storage.line(row) {
+        for (out, &b) in out.iter_mut().zip(other.row(inner)) {
+          *out = *out + a * b;
+        }
+      }
+    }
+    Ok(product)
+  }
+}
+
+impl<T: Scalar> CscMatrix<T> {
+  pub fn from_dense(dense: &Matrix<T>) -> CscMatrix<T> {
+    CsrMatrix::from_dense(dense).to_csc()
+  }
+
+  pub fn to_dense(&self) -> Matrix<T> {
+    let mut dense = Matrix::filled(self.rows, self.cols, T::ZERO);
+    for col in 0..self.cols {
+      for (row, &value) in self.storage.line(col) {
+        dense[(row, col)] = value;
+      }
+    }
+    dense
+  }
+
+  pub fn shape(&self) -> (usize, usize) {
+    (self.rows, self.cols)
+  }
+
+  /// How many elements are stored.
+  pub fn nnz(&self) -> usize {
+    self.storage.nnz()
+  }
+
+  /// The element at (row, col), or `None` if it is out of bounds.
+  pub fn get(&self, row: usize, col: usize) -> Option<T> {
+    if row >= self.rows || col >= self.cols {
+      return None;
+    }
+    Some(self.storage.get(col, row).copied().unwrap_or(T::ZERO))
+  }
+
+  /// The stored elements of column `col` as (row, value), top to bottom.
+  pub fn column(&self, col: usize) -> impl Iterator<Item = (usize, &T)> {
+    self.storage.line(col)
+  }
+
+  /// The same matrix, stored by rows.
+  pub fn to_csr(&self) -> CsrMatrix<T> {
+    CsrMatrix {
+      rows: self.rows,
+      cols: self.cols,
+      storage: self.storage.transpose(self.rows),
+    }
+  }
+
+  /// The `cols` x `rows` matrix with rows and columns swapped.
+  pub fn transpose(&self) -> CscMatrix<T> {
+    CscMatrix {
+      rows: self.cols,
+      cols: self.rows,
+      storage: self.storage.transpose(self.rows),
+    }
+  }
+
+  /// The product `self * other` with a dense matrix.
+  pub fn checked_mul(&self, other: &Matrix<T>) -> Result<Matrix<T>, MatrixError> {
+    if self.cols != other.rows() {
+      return Err(MatrixError::DimensionMismatch {
+        operation: "multiply",
+        left: self.shape(),
+        right: other.shape(),
+      });
+    }
+    let mut product = Matrix::filled(self.rows, other.cols(), T::ZERO);
+    // Column `inner` of `self` scales row `inner` of `other` into each row
+    // it has an entry in.
+    for inner in 0..self.cols {
+      for (row, &a) in self.storage.line(inner) {
+        for (out, &b) in product.row_mut(row).iter_mut().zip(other.row(inner)) {
+          *out = *out + a * b;
+        }
+      }
+    }
+    Ok(product)
+  }
+}
+
+impl<T: Scalar> Mul<&Matrix<T>> for &CsrMatrix<T> {
+  type Output = Matrix<T>;
+
+  fn mul(self, other: &Matrix<T>) -> Matrix<T> {
+    self
+      .checked_mul(other)
+      .unwrap_or_else(|error| panic!("{error}"))
+  }
+}
+
+impl<T: Scalar> Mul<&Matrix<T>> for &CscMatrix<T> {
+  type Output = Matrix<T>;
+
+  fn mul(self, other: &Matrix<T>) -> Matrix<T> {
+    self
+      .checked_mul(other)
+      .unwrap_or_else(|error| panic!("{error}"))
+  }
+}
+
+impl<T: Scalar + fmt::Display> Grid for CsrMatrix<T> {
+  type Element = T;
+
+  fn shape(&self) -> (usize, usize) {
+    CsrMatrix::shape(self)
+  }
+
+  fn element(&self, row: usize, col: usize) -> Cow<'_, T> {
+    match self.storage.get(row, col) {
+      Some(value) => Cow::Borrowed(value),
+      None => Cow::Owned(T::ZERO),
+    }
+  }
+}
+
+impl<T: Scalar + fmt::Display> Grid for CscMatrix<T> {
+  type Element = T;
+
+  fn shape(&self) -> (usize, usize) {
+    CscMatrix::shape(self)
+  }
+
+  fn element(&self, row: usize, col: usize) -> Cow<'_, T> {
+    match self.storage.get(col, row) {
+      Some(value) => Cow::Borrowed(value),
+      None => Cow::Owned(T::ZERO),
+    }
+  }
+}
+
+/// Like a dense `Matrix`, with the zeros written out.
+impl<T: Scalar + fmt::Display> fmt::Display for CsrMatrix<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let printer = PrettyPrinter {
+      precision: f.precision(),
+      ..PrettyPrinter::default()
+    };
+    f.write_str(&printer.render(self))
+  }
+}
+
+/// Like a dense `Matrix`, with the zeros written out.
+impl<T: Scalar + fmt::Display> fmt::Display for CscMatrix<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let printer = PrettyPrinter {
+      precision: f.precision(),
+      ..PrettyPrinter::default()
+    };
+    f.write_str(&printer.render(self))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use proptest::prelude::*;
+
+  use super::super::testing::{assert_transposed, example, mostly_zeros};
+  use super::*;
+
+  /// An adjacency matrix: 0 -> 1, 0 -> 3, 2 -> 0, 3 -> 3.
+  fn graph() -> CooMatrix<i32> {
+    let mut coo = CooMatrix::new(4, 4);
+    for (from, to) in [(3, 3), (0, 3), (2, 0), (0, 1)] {
+      coo.push(from, to, 1).unwrap();
+    }
+    coo
+  }
+
+  #[test]
+  fn test_coo_builds_compressed_storage() {
+    let csr = graph().to_csr();
+
+    assert_eq!(csr.storage.offsets, vec![0, 2, 2, 3, 4]);
+    assert_eq!(csr.storage.indices, vec![1, 3, 0, 3]);
+    assert_eq!(csr.get(0, 3), Some(1));
+    assert_eq!(csr.get(1, 0), Some(0));
+    assert_eq!(csr.get(4, 0), None);
+    assert_eq!(csr.row(0).collect::<Vec<_>>(), vec![(1, &1), (3, &1)]);
+    let csc = graph().to_csc();
+    assert_eq!(csc.column(3).collect::<Vec<_>>(), vec![(0, &1), (3, &1)]);
+    assert_eq!(csc.to_dense(), csr.to_dense());
+  }
+
+  #[test]
+  fn test_coo_sums_repeated_entries() {
+    let mut coo = graph();
+    coo.push(0, 1, 5).unwrap();
+
+    assert_eq!(coo.entry_count(), 5);
+    assert_eq!(coo.to_csr().nnz(), 4);
+    assert_eq!(coo.to_csr().get(0, 1), Some(6));
+    assert_eq!(coo.to_csc().get(0, 1), Some(6));
+    assert_eq!(coo.to_dense()[(0, 1)], 6);
+    assert_eq!(
+      coo.push(4, 0, 1),
+      Err(MatrixError::OutOfBounds {
+        row: 4,
+        col: 0,
+        rows: 4,
+        cols: 4
+      })
+    );
+  }
+
+  #[test]
+  fn test_transpose() {
+    let matrix = example();
+    let csr = CsrMatrix::from_dense(&matrix);
+
+    assert_transposed(&matrix, &csr.transpose().to_dense());
+    assert_transposed(&matrix, &csr.to_csc().transpose().to_dense());
+    assert_eq!(csr.to_csc().to_csr(), csr);
+  }
+
+  #[test]
+  fn test_prints_like_a_dense_matrix() {
+    let csr = graph().to_csr();
+    let printer = PrettyPrinter::default();
+
+    assert_eq!(csr.to_string(), csr.to_dense().to_string());
+    assert_eq!(printer.render(&csr.to_csc()), printer.render(&csr));
+    assert_eq!(printer.to_csv(&csr), "0,1,0,1\n0,0,0,0\n1,0,0,0\n0,0,0,1\n");
+  }
+
+  #[test]
+  fn test_multiplying_mismatched_shapes_fails() {
+    let csr = graph().to_csr();
+    assert!(matches!(
+      csr.checked_mul(&Matrix::filled(3, 2, 1)),
+      Err(MatrixError::DimensionMismatch { .. })
+    ));
+    assert!(csr.to_csc().checked_mul(&Matrix::filled(3, 2, 1)).is_err());
+  }
+
+  proptest! {
+    #[test]
+    fn test_dense_round_trip(matrix in (0..8usize, 0..8usize)
+      .prop_flat_map(|(rows, cols)| mostly_zeros(rows, cols))) {
+      let csr = CsrMatrix::from_dense(&matrix);
+      let zeros = matrix.as_slice().iter().filter(|&&x| x == 0).count();
+      prop_assert_eq!(csr.nnz(), matrix.as_slice().len() - zeros);
+      prop_assert_eq!(csr.to_dense(), matrix.clone());
+      prop_assert_eq!(CscMatrix::from_dense(&matrix).to_dense(), matrix.clone());
+      assert_transposed(&matrix, &csr.transpose().to_dense());
+      assert_transposed(&matrix, &CscMatrix::from_dense(&matrix).transpose().to_dense());
+    }
+
+    #[test]
+    fn test_sparse_dense_product((a, b) in (1..8usize, 1..8usize, 1..8usize)
+      .prop_flat_map(|(n, m, p)| (mostly_zeros(n, m), mostly_zeros(m, p)))) {
+      let expected = &a * &b;
+      prop_assert_eq!(&CsrMatrix::from_dense(&a) * &b, expected.clone());
+      prop_assert_eq!(&CscMatrix::from_dense(&a) * &b, expected);
+    }
+  }
+}
@@ -0,0 +1,35 @@
+// Test helpers
+// Matrices and checks shared by the tests of the dense and sparse matrix
+// types, and by `test_transpose` in `main.rs`.
+use std::fmt::Debug;
+
+use proptest::prelude::*;
+
+use super::Matrix;
+
+/// The 3x3 matrix the exercises transpose.
+pub fn example() -> Matrix<i32> {
+  Matrix::from([
+    [101, 102, 103], //
+    [201, 202, 203],
+    [301, 302, 303],
+  ])
+}
+
+/// Check that `transposed` is `matrix` with its rows and columns swapped,
+/// element by element rather than through another transpose.
+pub fn assert_transposed<T: PartialEq + Debug>(matrix: &Matrix<T>, transposed: &Matrix<T>) {
+  assert_eq!(transposed.shape(), (matrix.cols(), matrix.rows()));
+  for row in 0..matrix.rows() {
+    for col in 0..matrix.cols() {
+      assert_eq!(matrix[(row, col)], transposed[(col, row)], "({row}, {col})");
+    }
+  }
+}
+
+/// A `rows` x `cols` matrix of small integers, three quarters of them 0.
+pub fn mostly_zeros(rows: usize, cols: usize) -> impl Strategy<Value = Matrix<i64>> {
+  let element = prop_oneof![3 => Just(0i64), 1 => -9i64..10];
+  prop::collection::vec(element, rows * cols)
+    .prop_map(move |data| Matrix::from_vec(rows, cols, data).unwrap())
+}
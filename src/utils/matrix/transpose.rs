@@ -0,0 +1,153 @@
+// Transposes for large matrices
+// `Matrix::transpose` reads the source row by row and so writes the result
+// a column at a time, one element per cache line once the matrix outgrows
+// the cache. These variants keep both sides cache friendly:
+//
+// - `transpose_blocked` copies BLOCK x BLOCK tiles, small enough that the
+//   rows of a tile being read and the rows being written all stay cached.
+// - `transpose_in_place` needs no second matrix. A square matrix swaps
+//   elements across the diagonal, tile by tile. A rectangular one follows
+//   the cycles of the permutation taking each element to its new index,
+//   which costs a bit per element to remember which cycles are done.
+//
+// `benches/transpose.rs` compares them with the naive version.
+use super::Matrix;
+
+/// The side of the tiles, in elements. 32 rows of 32 `f64`s take 8KiB, so a
+/// source and a destination tile fit in any L1 cache.
+const BLOCK: usize = 32;
+
+impl<T: Clone> Matrix<T> {
+  /// The same matrix as `transpose`, copied a tile at a time.
+  pub fn transpose_blocked(&self) -> Matrix<T> {
+    let (rows, cols) = self.shape();
+    let Some(first) = self.data.first() else {
+      return Matrix::from_vec(cols, rows, Vec::new()).unwrap();
+    };
+    let mut transposed = Matrix::filled(cols, rows, first.clone());
+    for block_row in (0..rows).step_by(BLOCK) {
+      for block_col in (0..cols).step_by(BLOCK) {
+        for row in block_row..(block_row + BLOCK).min(rows) {
+          for col in block_col..(block_col + BLOCK).min(cols) {
+            transposed.data[col * rows + row] = self.data[row * cols + col].clone();
+          }
+        }
+      }
+    }
+    transposed
+  }
+}
+
+impl<T> Matrix<T> {
+  /// Transpose without allocating a second matrix. Any shape works, but
+  /// square matrices are much faster.
+  pub fn transpose_in_place(&mut self) {
+    if self.is_square() {
+      self.transpose_square_in_place();
+    } else {
+      self.transpose_cycles_in_place();
+    }
+    (self.rows, self.cols) = (self.cols, self.rows);
+  }
+
+  fn transpose_square_in_place(&mut self) {
+    let size = self.rows;
+    for block_row in (0..size).step_by(BLOCK) {
+      for block_col in (block_row..size).step_by(BLOCK) {
+        for row in block_row..(block_row + BLOCK).min(size) {
+          // Tiles on the diagonal swap with themselves, so only half of
+          // each one is visited.
+          let first_col = if block_row == block_col {
+            row + 1
+          } else {
+            block_col
+          };
+          for col in first_col..(block_col + BLOCK).min(size) {
+            self.data.swap(row * size + col, col * size + row);
+          }
+        }
+      }
+    }
+  }
+
+  /// The element at index `i` of an r x c matrix moves to `i * r mod
+  /// (r * c - 1)`, apart from the last one, which stays. Each cycle of that
+  /// permutation is rotated by swapping its elements through its first
+  /// slot.
+  fn transpose_cycles_in_place(&mut self) {
+    let len = self.data.len();
+    if len < 3 {
+      // A single row or column reads the same either way.
+      return;
+    }
+    let last = len - 1;
+    let destination = |index: usize| index * self.rows % last;
+    let mut done = vec![0u64; len.div_ceil(64)];
+    for start in 1..last {
+      if done[start / 64] & (1 << (start % 64)) != 0 {
+        continue;
+      }
+      // `start` holds the element that came from `index`.
+      let mut index = start;
+      loop {
+        done[index / 64] |= 1 << (index % 64);
+        let next = destination(index);
+        if next == start {
+          break;
+        }
+        self.data.swap(start, next);
+        index = next;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use proptest::prelude::*;
+
+  use super::*;
+
+  #[test]
+  fn test_variants_agree_with_transpose() {
+    for (rows, cols) in [
+      (0, 0),
+      (0, 3),
+      (1, 1),
+      (1, 5),
+      (5, 1),
+      (2, 3),
+      (33, 33),
+      (70, 45),
+    ] {
+      let matrix = Matrix::from_fn(rows, cols, |row, col| row * 1000 + col);
+      let expected = matrix.transpose();
+
+      assert_eq!(matrix.transpose_blocked(), expected, "{rows}x{cols}");
+      let mut in_place = matrix.clone();
+      in_place.transpose_in_place();
+      assert_eq!(in_place, expected, "{rows}x{cols}");
+    }
+  }
+
+  #[test]
+  fn test_in_place_moves_elements_without_cloning() {
+    let mut matrix = Matrix::from_fn(3, 4, |row, col| format!("{row},{col}"));
+    matrix.transpose_in_place();
+
+    assert_eq!(matrix.shape(), (4, 3));
+    assert_eq!(matrix[(3, 2)], "2,3");
+  }
+
+  proptest! {
+    #[test]
+    fn test_in_place_is_an_involution(rows in 1..80usize, cols in 1..80usize) {
+      let original = Matrix::from_fn(rows, cols, |row, col| (row, col));
+      let mut matrix = original.clone();
+      matrix.transpose_in_place();
+      prop_assert_eq!(&matrix, &original.transpose_blocked());
+      matrix.transpose_in_place();
+      prop_assert_eq!(matrix, original);
+    }
+  }
+}
@@ -0,0 +1,71 @@
+//! Runs the `link-checker` binary against a local server.
+use std::process::Command;
+
+use rust_wasm::link_checker::test_server::TestServer;
+
+#[test]
+fn test_stdout_is_only_the_report() {
+  let server = TestServer::new()
+    .route(
+      "/",
+      200,
+      r#"<a href="/ok">ok</a> <a href="/missing">missing</a>"#,
+    )
+    .route("/ok", 200, r#"<a href="/">home</a>"#)
+    .start();
+
+  for format in ["json", "sarif"] {
+    let output = Command::new(env!("CARGO_BIN_EXE_link-checker"))
+      .args([server.url("/").as_str(), "--format", format])
+      .output()
+      .unwrap();
+
+    assert_eq!(output.status.code(), Some(1), "{format}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let report: serde_json::Value = serde_json::from_str(&stdout)
+      .unwrap_or_else(|error| panic!("{format} report mixed with the log ({error}):\n{stdout}"));
+    assert!(
+      report.to_string().contains("/missing"),
+      "{format}: {report}"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Checking"), "{format}: {stderr}");
+  }
+}
+
+#[test]
+fn test_root_checks_a_site_on_disk() {
+  let root = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("link-checker-cli-root");
+  let _ = std::fs::remove_dir_all(&root);
+  std::fs::create_dir_all(root.join("guide")).unwrap();
+  std::fs::write(
+    root.join("index.html"),
+    r#"<a href="guide/">guide</a> <a href="/missing.html">missing</a>"#,
+  )
+  .unwrap();
+  std::fs::write(root.join("guide/index.html"), r#"<a href="../">home</a>"#).unwrap();
+
+  let output = Command::new(env!("CARGO_BIN_EXE_link-checker"))
+    .arg("--root")
+    .arg(&root)
+    .args([
+      "--site-url",
+      "https://docs.example.com/",
+      "--format",
+      "json",
+    ])
+    .output()
+    .unwrap();
+  std::fs::remove_dir_all(&root).unwrap();
+
+  assert_eq!(output.status.code(), Some(1));
+  let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+  assert_eq!(report["summary"]["checked"], 3, "{report}");
+  assert_eq!(report["summary"]["broken"], 1, "{report}");
+  assert!(
+    report
+      .to_string()
+      .contains("https://docs.example.com/missing.html"),
+    "{report}"
+  );
+}